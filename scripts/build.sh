@@ -2,5 +2,8 @@
 set -e
 cd "`dirname $0`"/..
 source scripts/flags.sh
+# the collection code is this contract without the factory feature; the factory embeds it
 cargo build --all --target wasm32-unknown-unknown --release
+cp target/wasm32-unknown-unknown/release/non_fungible_token_factory.wasm ./res/collection.wasm
+cargo build --all --target wasm32-unknown-unknown --release --features factory
 cp target/wasm32-unknown-unknown/release/*.wasm ./res/
\ No newline at end of file
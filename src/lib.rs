@@ -1,12 +1,14 @@
 use near_contract_standards::non_fungible_token::metadata::{
     NFTContractMetadata, TokenMetadata, NFT_METADATA_SPEC,
 };
-use near_contract_standards::non_fungible_token::NonFungibleToken;
-use near_contract_standards::non_fungible_token::TokenId;
+use near_contract_standards::non_fungible_token::{NonFungibleToken, Token, TokenId};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap};
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, UnorderedMap};
 use near_sdk::serde::Serialize;
-use near_sdk::{env, near_bindgen, AccountId, BorshStorageKey, PanicOnDefault, Promise};
+use near_sdk::{
+    env, near_bindgen, require, AccountId, Balance, BorshStorageKey, PanicOnDefault, Promise,
+    PublicKey,
+};
 use std::collections::HashMap;
 
 #[near_bindgen]
@@ -16,6 +18,33 @@ pub struct Contract {
     metadata: LazyOption<NFTContractMetadata>,
     expiration_timestamp: LookupMap<TokenId, u64>,
     royalty: LookupMap<TokenId, HashMap<AccountId, u32>>,
+    collections: UnorderedMap<AccountId, my_factory::CollectionInfo>,
+    renewers: LookupSet<AccountId>,
+    renewal_price: Balance,
+    minted_by: LookupMap<TokenId, AccountId>,
+    max_royalty: u32,
+    default_royalty: LazyOption<HashMap<AccountId, u32>>,
+    roles: LookupSet<(my_roles::Role, AccountId)>,
+    minter_quotas: LookupMap<AccountId, u64>,
+    paused: bool,
+    sale: LazyOption<my_sale::SaleConfig>,
+    sale_sold: u64,
+    sale_minted: LookupMap<AccountId, u64>,
+    sale_allowlist: LookupSet<AccountId>,
+    next_token_id: u64,
+    token_id_prefix: String,
+    metadata_history: LookupMap<TokenId, Vec<Vec<u8>>>,
+    start_timestamp: LookupMap<TokenId, u64>,
+    expiry_policy: Option<my_expiry::ExpiryPolicy>,
+    token_expiry_policies: LookupMap<TokenId, my_expiry::ExpiryPolicy>,
+    grace_period: u64,
+    token_grace_periods: LookupMap<TokenId, u64>,
+    private_metadata: LookupMap<TokenId, my_metadata::PrivateMetadata>,
+    metadata_keys: LookupMap<AccountId, PublicKey>,
+    expired_tokens: LookupSet<TokenId>,
+    expired_supply: u64,
+    expired_supply_per_owner: LookupMap<AccountId, u64>,
+    issued_by: LookupMap<TokenId, AccountId>,
 }
 
 const DATA_IMAGE_SVG_NEAR_ICON: &str = "data:image/svg+xml,%3Csvg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 288 288'%3E%3Cg id='l' data-name='l'%3E%3Cpath d='M187.58,79.81l-30.1,44.69a3.2,3.2,0,0,0,4.75,4.2L191.86,103a1.2,1.2,0,0,1,2,.91v80.46a1.2,1.2,0,0,1-2.12.77L102.18,77.93A15.35,15.35,0,0,0,90.47,72.5H87.34A15.34,15.34,0,0,0,72,87.84V201.16A15.34,15.34,0,0,0,87.34,216.5h0a15.35,15.35,0,0,0,13.08-7.31l30.1-44.69a3.2,3.2,0,0,0-4.75-4.2L96.14,186a1.2,1.2,0,0,1-2-.91V104.61a1.2,1.2,0,0,1,2.12-.77l89.55,107.23a15.35,15.35,0,0,0,11.71,5.43h3.13A15.34,15.34,0,0,0,216,201.16V87.84A15.34,15.34,0,0,0,200.66,72.5h0A15.35,15.35,0,0,0,187.58,79.81Z'/%3E%3C/g%3E%3C/svg%3E";
@@ -30,6 +59,24 @@ enum StorageKey {
     Timestamp,
     Royalty,
     TokensPerOwner { account_hash: Vec<u8> },
+    Collections,
+    Renewers,
+    MintedBy,
+    Roles,
+    MinterQuotas,
+    Sale,
+    SaleMinted,
+    SaleAllowlist,
+    MetadataHistory,
+    StartTimestamp,
+    TokenExpiryPolicies,
+    TokenGracePeriods,
+    PrivateMetadata,
+    MetadataKeys,
+    ExpiredTokens,
+    ExpiredSupplyPerOwner,
+    IssuedBy,
+    DefaultRoyalty,
 }
 
 #[near_bindgen]
@@ -47,13 +94,26 @@ impl Contract {
                 reference: None,
                 reference_hash: None,
             },
+            None,
         )
     }
 
+    /// `max_royalty` caps the total perpetual royalty of a token in basis points
+    /// (10_000 if not given).
     #[init]
-    pub fn new(owner_id: AccountId, metadata: NFTContractMetadata) -> Self {
+    pub fn new(
+        owner_id: AccountId,
+        metadata: NFTContractMetadata,
+        max_royalty: Option<u32>,
+    ) -> Self {
         assert!(!env::state_exists(), "Already initialized");
         metadata.assert_valid();
+        let max_royalty = max_royalty.unwrap_or(my_royalty::MAX_TOTAL_ROYALTY);
+        require!(
+            max_royalty <= my_royalty::MAX_TOTAL_ROYALTY,
+            "Maximum royalty can't exceed 10000 basis points"
+        );
+        my_upgrade::write_state_version();
         Self {
             tokens: NonFungibleToken::new(
                 StorageKey::NonFungibleToken,
@@ -65,6 +125,33 @@ impl Contract {
             metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
             expiration_timestamp: LookupMap::new(StorageKey::Timestamp),
             royalty: LookupMap::new(StorageKey::Royalty),
+            collections: UnorderedMap::new(StorageKey::Collections),
+            renewers: LookupSet::new(StorageKey::Renewers),
+            renewal_price: 0,
+            minted_by: LookupMap::new(StorageKey::MintedBy),
+            max_royalty,
+            default_royalty: LazyOption::new(StorageKey::DefaultRoyalty, None),
+            roles: LookupSet::new(StorageKey::Roles),
+            minter_quotas: LookupMap::new(StorageKey::MinterQuotas),
+            paused: false,
+            sale: LazyOption::new(StorageKey::Sale, None),
+            sale_sold: 0,
+            sale_minted: LookupMap::new(StorageKey::SaleMinted),
+            sale_allowlist: LookupSet::new(StorageKey::SaleAllowlist),
+            next_token_id: 0,
+            token_id_prefix: String::new(),
+            metadata_history: LookupMap::new(StorageKey::MetadataHistory),
+            start_timestamp: LookupMap::new(StorageKey::StartTimestamp),
+            expiry_policy: None,
+            token_expiry_policies: LookupMap::new(StorageKey::TokenExpiryPolicies),
+            grace_period: 0,
+            token_grace_periods: LookupMap::new(StorageKey::TokenGracePeriods),
+            private_metadata: LookupMap::new(StorageKey::PrivateMetadata),
+            metadata_keys: LookupMap::new(StorageKey::MetadataKeys),
+            expired_tokens: LookupSet::new(StorageKey::ExpiredTokens),
+            expired_supply: 0,
+            expired_supply_per_owner: LookupMap::new(StorageKey::ExpiredSupplyPerOwner),
+            issued_by: LookupMap::new(StorageKey::IssuedBy),
         }
     }
 }
@@ -77,30 +164,69 @@ pub struct JsonToken {
     pub metadata: Option<TokenMetadata>,
     pub approved_account_ids: Option<HashMap<AccountId, u64>>,
     pub expiration_date: Option<u64>,
+    pub start_date: Option<u64>,
+    pub status: TokenStatus,
+    /// The token has expired but is still within its grace period: it can be renewed, but
+    /// not transferred or sold.
+    pub in_grace: bool,
     pub royalty: HashMap<AccountId, u32>,
 }
 
-near_contract_standards::impl_non_fungible_token_approval!(Contract, tokens);
+/// Where a token is in its validity window: it is `pending` before its start date,
+/// `active` until its expiration date and `expired` after that.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenStatus {
+    Pending,
+    Active,
+    Expired,
+}
+
+impl From<JsonToken> for Token {
+    fn from(token: JsonToken) -> Self {
+        Token {
+            token_id: token.token_id,
+            owner_id: token.owner_id,
+            metadata: token.metadata,
+            approved_account_ids: token.approved_account_ids,
+        }
+    }
+}
 
+pub mod my_approval;
+pub mod my_burn;
 pub mod my_core;
 pub mod my_enumeration;
+pub mod my_events;
+pub mod my_expiry;
 pub mod my_extra;
+pub mod my_factory;
 mod my_internal;
+pub mod my_metadata;
 pub mod my_mint;
+pub mod my_renewal;
+pub mod my_roles;
 pub mod my_royalty;
+pub mod my_sale;
+pub mod my_upgrade;
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
-    use near_sdk::json_types::U128;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::testing_env;
+    use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
+    use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+    use near_contract_standards::non_fungible_token::enumeration::NonFungibleTokenEnumeration;
+    use near_sdk::json_types::{Base64VecU8, U128};
+    use near_sdk::test_utils::{self, accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
     use std::collections::HashMap;
 
     use super::*;
 
     const MIN_REQUIRED_APPROVAL_YOCTO: u128 = 150000000000000000000;
-    const MINT_STORAGE_COST: u128 = 6370000000000000000000;
-    const MINT_WITH_DATE_STORAGE_COST: u128 = 6910000000000000000000;
+    const MINT_STORAGE_COST: u128 = 6920000000000000000000;
+    const MINT_WITH_DATE_STORAGE_COST: u128 = 7620000000000000000000;
+    const SALE_MINT_STORAGE_COST: u128 = 8480000000000000000000;
 
     fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
@@ -111,15 +237,30 @@ mod tests {
         builder
     }
 
-    fn sample_token_metadata() -> TokenMetadata {
+    fn sample_token_metadata() -> my_metadata::PrivateMetadata {
+        my_metadata::PrivateMetadata {
+            ciphertext: Base64VecU8::from(b"encrypted Olympus Mons".to_vec()),
+            hash: Base64VecU8::from(env::sha256(b"Olympus Mons")),
+        }
+    }
+
+    /// The metadata views return for a token: only the dates maintained by the contract.
+    fn metadata_patch(metadata: my_metadata::PrivateMetadata) -> my_metadata::TokenMetadataPatch {
+        my_metadata::TokenMetadataPatch {
+            metadata: Some(metadata),
+            issued_at: None,
+        }
+    }
+
+    fn public_token_metadata(issued_at: &str, expires_at: Option<&str>) -> TokenMetadata {
         TokenMetadata {
-            title: Some("Olympus Mons".into()),
-            description: Some("The tallest mountain in the charted solar system".into()),
+            title: None,
+            description: None,
             media: None,
             media_hash: None,
-            copies: Some(1u64),
-            issued_at: None,
-            expires_at: None,
+            copies: None,
+            issued_at: Some(issued_at.to_string()),
+            expires_at: expires_at.map(String::from),
             starts_at: None,
             updated_at: None,
             extra: None,
@@ -128,11 +269,79 @@ mod tests {
         }
     }
 
+    fn metadata_keypair(account_id: &AccountId) -> ed25519_dalek::Keypair {
+        let secret =
+            ed25519_dalek::SecretKey::from_bytes(&env::sha256(account_id.as_bytes())).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        ed25519_dalek::Keypair { secret, public }
+    }
+
+    fn register_metadata_key(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        account_id: AccountId,
+    ) {
+        let public_key = metadata_keypair(&account_id).public.to_bytes();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR / 100)
+            .predecessor_account_id(account_id)
+            .build());
+        contract.set_metadata_key(PublicKey::try_from([&[0], &public_key[..]].concat()).unwrap());
+    }
+
+    /// Reads the metadata of `token_id` with a request signed by `account_id` now.
+    fn read_token_metadata(
+        contract: &Contract,
+        token_id: &str,
+        account_id: AccountId,
+    ) -> Option<my_metadata::PrivateMetadata> {
+        use ed25519_dalek::Signer;
+        let signed_at = env::block_timestamp() / 1_000_000;
+        let message = format!(
+            "nft_token_metadata:{}:{}:{}:{}",
+            env::current_account_id(),
+            token_id,
+            account_id,
+            signed_at
+        );
+        let signature = metadata_keypair(&account_id).sign(message.as_bytes());
+        contract.nft_token_metadata(
+            token_id.to_string(),
+            account_id,
+            signed_at,
+            Base64VecU8::from(signature.to_bytes().to_vec()),
+        )
+    }
+
+    fn mint_token(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        token_id: &str,
+        expiration_period: Option<&str>,
+    ) {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.nft_mint(
+            Some(token_id.to_string()),
+            accounts(0),
+            sample_token_metadata(),
+            expiration_period.map(String::from),
+            None,
+            None,
+            None,
+        );
+    }
+
     #[test]
     fn test_new() {
         let mut context = get_context(accounts(1));
         testing_env!(context.build());
-        let contract = Contract::new_default_meta(accounts(1).into());
+        let contract = Contract::new_default_meta(accounts(1));
         testing_env!(context.is_view(true).build());
         assert_eq!(contract.nft_token("1".to_string()), None);
     }
@@ -149,7 +358,7 @@ mod tests {
     fn test_mint_with_expiration_date() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -161,11 +370,13 @@ mod tests {
         let token_id = "0".to_string();
         let expiration_time = 2;
         let token = contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             Some(String::from("2s")),
             None,
+            None,
+            None,
         );
         assert!(
             token.expiration_date.is_some(),
@@ -177,7 +388,10 @@ mod tests {
         );
         assert_eq!(token.token_id, token_id);
         assert_eq!(token.owner_id.to_string(), accounts(0).to_string());
-        assert_eq!(token.metadata.unwrap(), sample_token_metadata());
+        // expires_at of the metadata is the expiration date in milliseconds
+        let metadata = token.metadata.unwrap();
+        assert_eq!(metadata.expires_at, Some("2000".into()));
+        assert_eq!(metadata.title, None);
         assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
     }
 
@@ -185,7 +399,7 @@ mod tests {
     fn test_mint_without_date() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -195,15 +409,19 @@ mod tests {
 
         let token_id = "0".to_string();
         let token = contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             None,
             None,
+            None,
+            None,
         );
         assert_eq!(token.token_id, token_id);
         assert_eq!(token.owner_id.to_string(), accounts(0).to_string());
-        assert_eq!(token.metadata.unwrap(), sample_token_metadata());
+        let metadata = token.metadata.unwrap();
+        assert_eq!(metadata.expires_at, None);
+        assert_eq!(metadata.title, None);
         assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
     }
 
@@ -211,7 +429,7 @@ mod tests {
     fn test_get_token_by_owner() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -221,20 +439,28 @@ mod tests {
             .build());
         let token_id = "0".to_string();
         let token = contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             Some(String::from("5m")),
             None,
+            None,
+            None,
+        );
+        assert_eq!(Some(token), contract.nft_token_extended(token_id.clone()));
+
+        register_metadata_key(&mut context, &mut contract, accounts(0));
+        assert_eq!(
+            read_token_metadata(&contract, &token_id, accounts(0)),
+            Some(sample_token_metadata())
         );
-        assert_eq!(Some(token), contract.nft_token(token_id.clone()));
     }
 
     #[test]
     fn test_get_token_not_by_owner() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -244,29 +470,27 @@ mod tests {
             .build());
         let token_id = "0".to_string();
         let token = contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             Some(String::from("5m")),
             None,
+            None,
+            None,
         );
-        let new_token = JsonToken {
-            metadata: None,
-            ..token
-        };
         testing_env!(context
             .storage_usage(env::storage_usage())
             .predecessor_account_id(accounts(1))
             .block_timestamp(0)
             .build());
-        assert_eq!(Some(new_token), contract.nft_token(token_id.clone()));
+        assert_eq!(Some(token), contract.nft_token_extended(token_id.clone()));
     }
 
     #[test]
     fn test_get_expired_token() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -276,11 +500,13 @@ mod tests {
             .build());
         let token_id = "0".to_string();
         contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             Some(String::from("5m")),
             None,
+            None,
+            None,
         );
 
         testing_env!(context
@@ -296,7 +522,7 @@ mod tests {
     fn test_transfer() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -306,11 +532,13 @@ mod tests {
             .build());
         let token_id = "0".to_string();
         contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             Some(String::from("5m")),
             None,
+            None,
+            None,
         );
 
         testing_env!(context
@@ -327,16 +555,22 @@ mod tests {
             .attached_deposit(0)
             .build());
 
-        if let Some(token) = contract.nft_token(token_id.clone()) {
+        if let Some(token) = contract.nft_token_extended(token_id.clone()) {
             assert!(token.expiration_date.is_some());
             assert_eq!(token.expiration_date.unwrap() / 1_000_000_000, 300);
             assert_eq!(token.token_id, token_id);
             assert_eq!(token.owner_id.to_string(), accounts(1).to_string());
-            assert_eq!(token.metadata.unwrap(), sample_token_metadata());
+            assert_eq!(token.metadata.unwrap().title, None);
             assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
         } else {
             panic!("token not correctly created, or not found by nft_token");
         }
+
+        register_metadata_key(&mut context, &mut contract, accounts(1));
+        assert_eq!(
+            read_token_metadata(&contract, &token_id, accounts(1)),
+            Some(sample_token_metadata())
+        );
     }
 
     #[test]
@@ -344,7 +578,7 @@ mod tests {
         use crate::my_royalty::Payouts;
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -353,11 +587,13 @@ mod tests {
             .build());
         let token_id = "0".to_string();
         contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             None,
             None,
+            None,
+            None,
         );
 
         // alice approves bob
@@ -377,7 +613,7 @@ mod tests {
     fn test_nft_approve() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -386,11 +622,13 @@ mod tests {
             .build());
         let token_id = "0".to_string();
         contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             None,
             None,
+            None,
+            None,
         );
 
         testing_env!(context
@@ -413,7 +651,7 @@ mod tests {
     fn test_nft_revoke() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -422,11 +660,13 @@ mod tests {
             .build());
         let token_id = "0".to_string();
         contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             None,
             None,
+            None,
+            None,
         );
 
         // alice approves bob
@@ -457,7 +697,7 @@ mod tests {
     fn test_revoke_all() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -466,11 +706,13 @@ mod tests {
             .build());
         let token_id = "0".to_string();
         contract.nft_mint(
-            token_id.clone(),
+            Some(token_id.clone()),
             accounts(0),
             sample_token_metadata(),
             None,
             None,
+            None,
+            None,
         );
 
         // alice approves bob
@@ -496,4 +738,2547 @@ mod tests {
             .build());
         assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
     }
+
+    #[test]
+    fn test_create_collection() {
+        use near_contract_standards::non_fungible_token::metadata::NonFungibleTokenMetadataProvider;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(42)
+            .build());
+        contract.create_collection("drop".to_string(), accounts(1), contract.nft_metadata());
+
+        let collection_id: AccountId = format!("drop.{}", accounts(0)).parse().unwrap();
+        let expected = my_factory::JsonCollection {
+            collection_id: collection_id.clone(),
+            owner_id: accounts(1),
+            creator_id: accounts(2),
+            created_at: 42,
+        };
+        assert_eq!(
+            contract.get_collection(collection_id),
+            Some(expected.clone())
+        );
+        assert_eq!(contract.get_collections(None, None), vec![expected]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach at least")]
+    fn test_create_collection_without_deposit() {
+        use near_contract_standards::non_fungible_token::metadata::NonFungibleTokenMetadataProvider;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.create_collection("drop".to_string(), accounts(1), contract.nft_metadata());
+    }
+
+    #[test]
+    fn test_create_collection_rollback() {
+        use near_contract_standards::non_fungible_token::metadata::NonFungibleTokenMetadataProvider;
+        use near_sdk::{PromiseResult, RuntimeFeesConfig, VMConfig};
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.create_collection("drop".to_string(), accounts(1), contract.nft_metadata());
+
+        // the deploy batch failed, so the callback has to forget the collection
+        testing_env!(
+            context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(0)
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let collection_id: AccountId = format!("drop.{}", accounts(0)).parse().unwrap();
+        assert!(!contract.on_collection_created(
+            collection_id.clone(),
+            accounts(2),
+            U128(ONE_NEAR)
+        ));
+        assert_eq!(contract.get_collection(collection_id), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is expired")]
+    fn test_transfer_expired_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        contract.nft_transfer(accounts(1), "0".to_string(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is expired")]
+    fn test_transfer_call_expired_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        contract.nft_transfer_call(
+            accounts(1),
+            "0".to_string(),
+            None,
+            None,
+            "message".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is expired")]
+    fn test_approve_expired_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MIN_REQUIRED_APPROVAL_YOCTO)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        contract.nft_approve("0".to_string(), accounts(1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is expired")]
+    fn test_payout_expired_token() {
+        use crate::my_royalty::Payouts;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        contract.nft_payout("0".to_string(), U128(10), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is expired")]
+    fn test_transfer_payout_expired_token() {
+        use crate::my_royalty::Payouts;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        // alice approves bob while the token is still valid
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MIN_REQUIRED_APPROVAL_YOCTO)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.nft_approve("0".to_string(), accounts(1), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        contract.nft_transfer_payout(accounts(2), "0".to_string(), 1, None, U128(10), 1);
+    }
+
+    #[test]
+    fn test_renew_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.set_renewal_price(U128(10));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(15)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(60 * 1_000_000_000)
+            .build());
+        let expiration_date = contract.nft_renew("0".to_string(), Some("10m".to_string()), None);
+        assert_eq!(expiration_date / 1_000_000_000, 15 * 60);
+        assert_eq!(
+            contract
+                .nft_token_extended("0".to_string())
+                .unwrap()
+                .expiration_date,
+            Some(expiration_date)
+        );
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"nft_factory","version":"1.0.0","event":"nft_renew","data":[{{"owner_id":"{}","token_id":"0","expiration_date":{}}}]}}"#,
+                accounts(0),
+                expiration_date
+            )]
+        );
+    }
+
+    #[test]
+    fn test_renew_token_by_renewer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.add_renewer(accounts(2));
+        assert!(contract.is_renewer(accounts(2)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let expiration_date = contract.nft_renew("0".to_string(), Some("1h".to_string()), None);
+        assert_eq!(expiration_date / 1_000_000_000, 65 * 60);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the token owner or a renewer can renew the token")]
+    fn test_renew_token_not_by_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_renew("0".to_string(), Some("1h".to_string()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach 10 yoctoNEAR to renew the token")]
+    fn test_renew_token_without_payment() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.set_renewal_price(U128(10));
+        contract.nft_renew("0".to_string(), Some("1h".to_string()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is expired")]
+    fn test_renew_expired_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        contract.nft_renew("0".to_string(), Some("1h".to_string()), None);
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+        mint_token(&mut context, &mut contract, "1", Some("5m"));
+        mint_token(&mut context, &mut contract, "2", None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        let purged = contract.nft_purge_expired(None, 1);
+        assert_eq!(
+            purged,
+            my_burn::PurgeResult {
+                burned: 1,
+                reverted: 0,
+                next_token_id: Some("0".to_string())
+            }
+        );
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"nep171","version":"1.0.0","event":"nft_burn","data":[{{"owner_id":"{}","token_ids":["0"]}}]}}"#,
+                accounts(0)
+            )]
+        );
+        let purged = contract.nft_purge_expired(purged.next_token_id, 10);
+        assert_eq!(
+            purged,
+            my_burn::PurgeResult {
+                burned: 1,
+                reverted: 0,
+                next_token_id: None
+            }
+        );
+        assert_eq!(contract.nft_purge_expired(None, 10).burned, 0);
+
+        assert!(!contract.tokens.owner_by_id.contains_key(&"0".to_string()));
+        assert!(!contract.tokens.owner_by_id.contains_key(&"1".to_string()));
+        assert!(contract
+            .expiration_timestamp
+            .get(&"1".to_string())
+            .is_none());
+        assert!(contract.royalty.get(&"1".to_string()).is_none());
+        let tokens = contract.nft_tokens_for_owner(accounts(0), None, None);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_id, "2".to_string());
+    }
+
+    #[test]
+    fn test_burn() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_burn("0".to_string(), Some("burn".to_string()));
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"nep171","version":"1.0.0","event":"nft_burn","data":[{{"owner_id":"{}","token_ids":["0"],"memo":"burn"}}]}}"#,
+                accounts(0)
+            )]
+        );
+        assert_eq!(contract.nft_token("0".to_string()), None);
+        assert!(contract
+            .expiration_timestamp
+            .get(&"0".to_string())
+            .is_none());
+        assert!(contract
+            .nft_tokens_for_owner(accounts(0), None, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_burn_by_approved_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MIN_REQUIRED_APPROVAL_YOCTO)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve("0".to_string(), accounts(1), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_burn("0".to_string(), None);
+        assert!(!contract.tokens.owner_by_id.contains_key(&"0".to_string()));
+        // the owner gets back the storage of the approvals, the caller the rest
+        let receivers: Vec<AccountId> = test_utils::get_created_receipts()
+            .into_iter()
+            .map(|receipt| receipt.receiver_id)
+            .collect();
+        assert_eq!(receivers, vec![accounts(0), accounts(1)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the token owner or an approved account can burn the token")]
+    fn test_burn_not_by_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_burn("0".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the token owner can read its metadata")]
+    fn test_get_token_metadata_not_by_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+
+        register_metadata_key(&mut context, &mut contract, accounts(1));
+        read_token_metadata(&contract, "0", accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid metadata request signature")]
+    fn test_get_token_metadata_with_invalid_signature() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+        register_metadata_key(&mut context, &mut contract, accounts(0));
+
+        let signed_at = env::block_timestamp() / 1_000_000;
+        contract.nft_token_metadata(
+            "0".to_string(),
+            accounts(0),
+            signed_at,
+            Base64VecU8::from(vec![0; 64]),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Metadata request signature has expired")]
+    fn test_get_token_metadata_with_stale_signature() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+        register_metadata_key(&mut context, &mut contract, accounts(0));
+
+        testing_env!(context
+            .block_timestamp((my_metadata::METADATA_SIGNATURE_TTL + 1) * 1_000_000)
+            .build());
+        contract.nft_token_metadata(
+            "0".to_string(),
+            accounts(0),
+            0,
+            Base64VecU8::from(vec![0; 64]),
+        );
+    }
+
+    #[test]
+    fn test_enumeration_hides_metadata() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+
+        testing_env!(context.is_view(true).attached_deposit(0).build());
+        assert!(contract.nft_tokens(None, None).iter().all(|token| token
+            .metadata
+            .as_ref()
+            .unwrap()
+            .title
+            .is_none()));
+        assert!(contract
+            .nft_tokens_for_owner(accounts(0), None, None)
+            .iter()
+            .all(|token| token.metadata.as_ref().unwrap().title.is_none()));
+        assert_eq!(
+            contract.nft_token_metadata_hash("0".to_string()),
+            Some(sample_token_metadata().hash)
+        );
+    }
+
+    #[test]
+    fn test_nft_token_standard_shape() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context.is_view(true).attached_deposit(0).build());
+        let expected = Token {
+            token_id: "0".to_string(),
+            owner_id: accounts(0),
+            metadata: Some(public_token_metadata("0", Some("300000"))),
+            approved_account_ids: Some(HashMap::new()),
+        };
+        assert_eq!(
+            near_sdk::serde_json::to_value(contract.nft_token("0".to_string())).unwrap(),
+            near_sdk::serde_json::to_value(Some(expected.clone())).unwrap()
+        );
+        assert_eq!(
+            Token::from(contract.nft_token_extended("0".to_string()).unwrap()).owner_id,
+            expected.owner_id
+        );
+        assert_eq!(contract.nft_tokens(None, None).len(), 1);
+        assert_eq!(contract.nft_total_supply(), U128(1));
+        assert_eq!(contract.nft_supply_for_owner(accounts(0)), U128(1));
+    }
+
+    #[test]
+    fn test_supply() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+        mint_token(&mut context, &mut contract, "1", Some("5m"));
+        mint_token(&mut context, &mut contract, "2", None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_transfer(accounts(1), "1".to_string(), None, None);
+        assert_eq!(contract.nft_total_supply(), U128(3));
+        assert_eq!(contract.nft_supply_for_owner(accounts(0)), U128(2));
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(1));
+        assert_eq!(contract.nft_supply_for_owner(accounts(2)), U128(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        assert_eq!(contract.nft_total_supply(), U128(3));
+        // expired tokens are counted until they are purged
+        assert_eq!(contract.nft_active_supply(None), U128(3));
+
+        contract.nft_purge_expired(None, 10);
+        assert_eq!(contract.nft_total_supply(), U128(1));
+        assert_eq!(contract.nft_supply_for_owner(accounts(0)), U128(1));
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(0));
+        assert_eq!(contract.nft_active_supply(None), U128(1));
+        assert_eq!(contract.nft_active_supply(Some(accounts(0))), U128(1));
+        assert_eq!(contract.nft_active_supply(Some(accounts(1))), U128(0));
+    }
+
+    #[test]
+    fn test_tokens_page() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+        mint_token(&mut context, &mut contract, "1", Some("5m"));
+        mint_token(&mut context, &mut contract, "2", Some("5m"));
+        mint_token(&mut context, &mut contract, "3", None);
+        mint_token(&mut context, &mut contract, "4", None);
+
+        testing_env!(context
+            .is_view(true)
+            .attached_deposit(0)
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        let token_ids = |page: &my_enumeration::TokenPage| -> Vec<String> {
+            page.tokens
+                .iter()
+                .map(|token| token.token_id.clone())
+                .collect()
+        };
+
+        // every page looks at `limit` tokens, so the hidden ones make it shorter
+        let page = contract.nft_tokens_page(None, Some(2), Some(false));
+        assert_eq!(token_ids(&page), vec!["0"]);
+        assert_eq!(page.next_token_id, Some("1".to_string()));
+        let page = contract.nft_tokens_page(page.next_token_id, Some(2), None);
+        assert_eq!(token_ids(&page), vec!["3"]);
+        assert_eq!(page.next_token_id, Some("3".to_string()));
+        let page = contract.nft_tokens_page(page.next_token_id, Some(2), None);
+        assert_eq!(token_ids(&page), vec!["4"]);
+        assert_eq!(page.next_token_id, None);
+
+        let page = contract.nft_tokens_page(Some("0".to_string()), Some(2), Some(true));
+        assert_eq!(token_ids(&page), vec!["1", "2"]);
+
+        let page =
+            contract.nft_tokens_for_owner_page(accounts(0), Some("1".to_string()), Some(2), None);
+        assert_eq!(token_ids(&page), vec!["3"]);
+        assert_eq!(page.next_token_id, Some("3".to_string()));
+
+        // positions of the standard views count every token, so pages don't shift either
+        let token_ids = |tokens: Vec<Token>| -> Vec<String> {
+            tokens.into_iter().map(|token| token.token_id).collect()
+        };
+        assert!(contract.nft_tokens(Some(U128(1)), Some(2)).is_empty());
+        assert_eq!(
+            token_ids(contract.nft_tokens(Some(U128(2)), Some(3))),
+            vec!["3", "4"]
+        );
+        assert_eq!(
+            token_ids(contract.nft_tokens_for_owner(accounts(0), Some(U128(2)), Some(3))),
+            vec!["3", "4"]
+        );
+    }
+
+    #[test]
+    fn test_tokens_page_limit_is_capped() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        for token_id in 0..=my_enumeration::MAX_PAGE_SIZE {
+            mint_token(&mut context, &mut contract, &token_id.to_string(), None);
+        }
+
+        let page = contract.nft_tokens_page(None, Some(u64::MAX), None);
+        assert_eq!(page.tokens.len() as u64, my_enumeration::MAX_PAGE_SIZE);
+        assert!(page.next_token_id.is_some());
+        let page = contract.nft_tokens_page(page.next_token_id, None, None);
+        assert_eq!(page.tokens.len(), 1);
+        assert_eq!(page.next_token_id, None);
+    }
+
+    fn mint_token_with_royalties(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        royalties: HashMap<AccountId, u32>,
+    ) {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            Some("0".to_string()),
+            accounts(0),
+            sample_token_metadata(),
+            None,
+            Some(royalties),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_payout_with_full_royalties() {
+        use crate::my_royalty::Payouts;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token_with_royalties(
+            &mut context,
+            &mut contract,
+            HashMap::from([(accounts(1), 4000), (accounts(2), 6000)]),
+        );
+
+        let payout = contract.nft_payout("0".to_string(), U128(10_000), 3);
+        let expected = HashMap::from([
+            (accounts(0), U128(0)),
+            (accounts(1), U128(4000)),
+            (accounts(2), U128(6000)),
+        ]);
+        assert_eq!(payout.payout, expected);
+    }
+
+    #[test]
+    fn test_payout_with_owner_royalty() {
+        use crate::my_royalty::Payouts;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token_with_royalties(
+            &mut context,
+            &mut contract,
+            HashMap::from([(accounts(0), 1000), (accounts(1), 2500)]),
+        );
+
+        // the royalty of the current owner is part of their share
+        let payout = contract.nft_payout("0".to_string(), U128(100), 2);
+        let expected = HashMap::from([(accounts(0), U128(75)), (accounts(1), U128(25))]);
+        assert_eq!(payout.payout, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "Total royalty of 10001 basis points exceeds the maximum of 10000")]
+    fn test_mint_royalties_over_100_percent() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token_with_royalties(
+            &mut context,
+            &mut contract,
+            HashMap::from([(accounts(1), 5000), (accounts(2), 5001)]),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Royalty of bob must be greater than zero")]
+    fn test_mint_zero_royalty() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token_with_royalties(
+            &mut context,
+            &mut contract,
+            HashMap::from([(accounts(1), 0)]),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot add more than 6 perpetual royalty amounts")]
+    fn test_mint_too_many_royalties() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let royalties = (0..7)
+            .map(|i| (format!("artist{}", i).parse().unwrap(), 100))
+            .collect();
+        mint_token_with_royalties(&mut context, &mut contract, royalties);
+    }
+
+    #[test]
+    #[should_panic(expected = "Total royalty of 1001 basis points exceeds the maximum of 1000")]
+    fn test_mint_royalties_over_contract_cap() {
+        use near_contract_standards::non_fungible_token::metadata::NonFungibleTokenMetadataProvider;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let metadata = Contract::new_default_meta(accounts(0)).nft_metadata();
+        let mut contract = Contract::new(accounts(0), metadata, Some(1000));
+        assert_eq!(contract.nft_max_royalty(), 1000);
+        mint_token_with_royalties(
+            &mut context,
+            &mut contract,
+            HashMap::from([(accounts(1), 1001)]),
+        );
+    }
+
+    #[test]
+    fn test_royalty_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token_with_royalties(
+            &mut context,
+            &mut contract,
+            HashMap::from([(accounts(1), 3000)]),
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_royalty_transfer("0".to_string(), accounts(1), accounts(2), 1000);
+        assert_eq!(
+            contract.nft_royalties("0".to_string()),
+            HashMap::from([(accounts(1), 2000), (accounts(2), 1000)])
+        );
+        assert_eq!(
+            test_utils::get_logs().last().unwrap(),
+            &format!(
+                r#"EVENT_JSON:{{"standard":"nft_factory","version":"1.0.0","event":"nft_royalty_transfer","data":[{{"token_id":"0","old_holder_id":"{}","new_holder_id":"{}","basis_points":1000}}]}}"#,
+                accounts(1),
+                accounts(2)
+            )
+        );
+
+        // moving the whole share removes the old holder
+        contract.nft_royalty_transfer("0".to_string(), accounts(1), accounts(2), 2000);
+        assert_eq!(
+            contract.nft_royalties("0".to_string()),
+            HashMap::from([(accounts(2), 3000)])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the royalty holder can transfer it")]
+    fn test_royalty_transfer_not_by_holder() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token_with_royalties(
+            &mut context,
+            &mut contract,
+            HashMap::from([(accounts(1), 3000)]),
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_royalty_transfer("0".to_string(), accounts(1), accounts(2), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "holds only 3000 basis points of the royalty")]
+    fn test_royalty_transfer_more_than_held() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token_with_royalties(
+            &mut context,
+            &mut contract,
+            HashMap::from([(accounts(1), 3000)]),
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_royalty_transfer("0".to_string(), accounts(1), accounts(2), 3001);
+    }
+
+    #[test]
+    fn test_collection_default_royalty() {
+        use crate::my_royalty::Payouts;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let initial_storage_usage = env::storage_usage();
+        contract.set_default_royalty(HashMap::from([(accounts(1), 500)]));
+        // the default royalty is stored, and paid for, as soon as it is set
+        assert!(env::storage_usage() > initial_storage_usage);
+        assert_eq!(
+            contract.nft_default_royalty(),
+            HashMap::from([(accounts(1), 500)])
+        );
+
+        mint_token(&mut context, &mut contract, "0", None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            Some("1".to_string()),
+            accounts(0),
+            sample_token_metadata(),
+            None,
+            Some(HashMap::new()),
+            None,
+            None,
+        );
+        assert!(contract.royalty.get(&"0".to_string()).is_none());
+
+        let payout = contract.nft_payout("0".to_string(), U128(100), 2);
+        let expected = HashMap::from([(accounts(0), U128(95)), (accounts(1), U128(5))]);
+        assert_eq!(payout.payout, expected);
+        // a token with its own (empty) royalty map ignores the default
+        let payout = contract.nft_payout("1".to_string(), U128(100), 2);
+        assert_eq!(payout.payout, HashMap::from([(accounts(0), U128(100))]));
+
+        // transferring a default royalty gives the token its own royalty map
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_royalty_transfer("0".to_string(), accounts(1), accounts(2), 500);
+        assert_eq!(
+            contract.nft_royalties("0".to_string()),
+            HashMap::from([(accounts(2), 500)])
+        );
+        assert_eq!(
+            contract.nft_default_royalty(),
+            HashMap::from([(accounts(1), 500)])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires the admin role")]
+    fn test_set_default_royalty_not_by_admin() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_default_royalty(HashMap::from([(accounts(1), 500)]));
+    }
+
+    #[test]
+    fn test_grant_minter_role() {
+        use crate::my_roles::Role;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.grant_role(accounts(1), Role::Minter);
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"nft_factory","version":"1.0.0","event":"role_granted","data":[{{"account_id":"{}","role":"minter","authorized_id":"{}"}}]}}"#,
+                accounts(1),
+                accounts(0)
+            )]
+        );
+        assert!(contract.has_role(accounts(1), Role::Minter));
+        assert!(!contract.has_role(accounts(1), Role::Admin));
+        assert!(contract.has_role(accounts(0), Role::Admin));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token = contract.nft_mint(
+            Some("0".to_string()),
+            accounts(2),
+            sample_token_metadata(),
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_eq!(token.owner_id, accounts(2));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.revoke_role(accounts(1), Role::Minter);
+        assert!(!contract.has_role(accounts(1), Role::Minter));
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires the minter role")]
+    fn test_mint_without_minter_role() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_mint(
+            Some("0".to_string()),
+            accounts(1),
+            sample_token_metadata(),
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting quota exhausted")]
+    fn test_minter_quota() {
+        use crate::my_roles::Role;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.grant_role(accounts(1), Role::Minter);
+        contract.set_minter_quota(accounts(1), Some(1));
+
+        for token_id in ["0", "1"] {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST)
+                .predecessor_account_id(accounts(1))
+                .build());
+            contract.nft_mint(
+                Some(token_id.to_string()),
+                accounts(1),
+                sample_token_metadata(),
+                None,
+                None,
+                None,
+                None,
+            );
+            assert_eq!(contract.get_minter_quota(accounts(1)), Some(0));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner can call this method")]
+    fn test_admin_cannot_grant_admin() {
+        use crate::my_roles::Role;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.grant_role(accounts(1), Role::Admin);
+
+        // admins manage minters and pausers, but not other admins
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.grant_role(accounts(2), Role::Minter);
+        assert!(contract.has_role(accounts(2), Role::Minter));
+        contract.grant_role(accounts(2), Role::Admin);
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is paused")]
+    fn test_transfer_when_paused() {
+        use crate::my_roles::Role;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.grant_role(accounts(3), Role::Pauser);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.pause();
+        assert!(contract.is_paused());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_transfer(accounts(1), "0".to_string(), None, None);
+    }
+
+    fn start_sale(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        per_account_limit: Option<u64>,
+        allowlist: Option<my_sale::Allowlist>,
+    ) {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.set_sale(my_sale::SaleConfig {
+            price: U128(ONE_NEAR),
+            max_supply: 3,
+            per_account_limit,
+            starts_at: Some(10),
+            ends_at: Some(100),
+            allowlist,
+            treasury_id: accounts(4),
+            token_metadata: sample_token_metadata(),
+            expiration_period: None,
+            token_expires_at: None,
+            token_starts_at: None,
+        });
+    }
+
+    fn buy_tokens(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        buyer_id: AccountId,
+        count: u64,
+        merkle_proof: Option<Vec<Base64VecU8>>,
+    ) -> Vec<JsonToken> {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(count as u128 * (ONE_NEAR + SALE_MINT_STORAGE_COST))
+            .predecessor_account_id(buyer_id.clone())
+            .block_timestamp(50)
+            .build());
+        contract.nft_mint_public(buyer_id, count, merkle_proof)
+    }
+
+    #[test]
+    fn test_mint_public() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "1", None);
+        start_sale(&mut context, &mut contract, None, None);
+
+        let tokens = buy_tokens(&mut context, &mut contract, accounts(1), 2, None);
+        // generated ids skip the explicitly minted "1"
+        let token_ids: Vec<TokenId> = tokens.into_iter().map(|token| token.token_id).collect();
+        assert_eq!(token_ids, vec!["0".to_string(), "2".to_string()]);
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(2));
+        assert_eq!(contract.get_sale_sold(), 2);
+        assert_eq!(contract.get_sale_minted(accounts(1)), 2);
+        assert!(test_utils::get_logs()[0].contains(r#""token_ids":["0","2"]"#));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough tokens left in the sale")]
+    fn test_mint_public_over_max_supply() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        start_sale(&mut context, &mut contract, None, None);
+
+        buy_tokens(&mut context, &mut contract, accounts(1), 2, None);
+        buy_tokens(&mut context, &mut contract, accounts(2), 2, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't buy more than 1 tokens per account")]
+    fn test_mint_public_over_account_limit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        start_sale(&mut context, &mut contract, Some(1), None);
+
+        buy_tokens(&mut context, &mut contract, accounts(1), 1, None);
+        buy_tokens(&mut context, &mut contract, accounts(1), 1, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sale has not started")]
+    fn test_mint_public_before_start() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        start_sale(&mut context, &mut contract, None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR + MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(5)
+            .build());
+        contract.nft_mint_public(accounts(1), 1, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach at least")]
+    fn test_mint_public_without_price() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        start_sale(&mut context, &mut contract, None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(50)
+            .build());
+        contract.nft_mint_public(accounts(1), 1, None);
+    }
+
+    #[test]
+    fn test_mint_public_allowlist() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        start_sale(
+            &mut context,
+            &mut contract,
+            None,
+            Some(my_sale::Allowlist::Accounts),
+        );
+        contract.add_to_allowlist(vec![accounts(1)]);
+        assert!(contract.is_allowlisted(accounts(1)));
+
+        let tokens = buy_tokens(&mut context, &mut contract, accounts(1), 1, None);
+        assert_eq!(tokens[0].owner_id, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is not on the allowlist")]
+    fn test_mint_public_not_allowlisted() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        start_sale(
+            &mut context,
+            &mut contract,
+            None,
+            Some(my_sale::Allowlist::Accounts),
+        );
+        contract.add_to_allowlist(vec![accounts(1)]);
+
+        buy_tokens(&mut context, &mut contract, accounts(2), 1, None);
+    }
+
+    #[test]
+    fn test_mint_public_merkle_allowlist() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        // two-leaf tree: root = sha256(sorted(leaf_1, leaf_2))
+        let leaf_1 = env::sha256(accounts(1).as_bytes());
+        let leaf_2 = env::sha256(accounts(2).as_bytes());
+        let mut leaves = [leaf_1.clone(), leaf_2.clone()];
+        leaves.sort();
+        let root = env::sha256(&leaves.concat());
+        start_sale(
+            &mut context,
+            &mut contract,
+            None,
+            Some(my_sale::Allowlist::MerkleRoot(root.into())),
+        );
+
+        let tokens = buy_tokens(
+            &mut context,
+            &mut contract,
+            accounts(2),
+            1,
+            Some(vec![leaf_1.into()]),
+        );
+        assert_eq!(tokens[0].owner_id, accounts(2));
+    }
+
+    fn batch_entry(token_id: &str, receiver_id: AccountId) -> my_mint::BatchMintEntry {
+        (
+            token_id.to_string(),
+            receiver_id,
+            sample_token_metadata(),
+            Some("1d".to_string()),
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_batch_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(3 * MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let tokens = contract.nft_batch_mint(vec![
+            batch_entry("0", accounts(1)),
+            batch_entry("1", accounts(2)),
+            batch_entry("2", accounts(1)),
+        ]);
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(contract.nft_total_supply(), U128(3));
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(2));
+
+        // one event with an entry per owner
+        let logs = test_utils::get_logs();
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].contains(r#"{"owner_id":"bob","token_ids":["0","2"]}"#));
+        assert!(logs[0].contains(r#"{"owner_id":"charlie","token_ids":["1"]}"#));
+    }
+
+    #[test]
+    #[should_panic(expected = "token_id 1 must be unique")]
+    fn test_batch_mint_duplicate_id() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(3 * MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_batch_mint(vec![
+            batch_entry("0", accounts(1)),
+            batch_entry("1", accounts(2)),
+            batch_entry("1", accounts(1)),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "token_id 0 must be unique")]
+    fn test_batch_mint_existing_id() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_batch_mint(vec![
+            batch_entry("1", accounts(1)),
+            batch_entry("0", accounts(1)),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting quota exhausted")]
+    fn test_batch_mint_over_quota() {
+        use crate::my_roles::Role;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.grant_role(accounts(1), Role::Minter);
+        contract.set_minter_quota(accounts(1), Some(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_batch_mint(vec![
+            batch_entry("0", accounts(1)),
+            batch_entry("1", accounts(1)),
+        ]);
+    }
+
+    #[test]
+    fn test_mint_with_generated_id() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "ticket-0", None);
+        contract.set_token_id_prefix("ticket-".to_string());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        // "ticket-0" is already taken by an explicitly named token
+        let token = contract.nft_mint(
+            None,
+            accounts(1),
+            sample_token_metadata(),
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_eq!(token.token_id, "ticket-1");
+        assert_eq!(contract.get_next_token_id(), 2);
+        assert_eq!(
+            contract.nft_token("ticket-1".to_string()).unwrap().owner_id,
+            accounts(1)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner can call this method")]
+    fn test_set_token_id_prefix_not_by_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_token_id_prefix("ticket-".to_string());
+    }
+
+    #[test]
+    fn test_batch_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+        mint_token(&mut context, &mut contract, "1", Some("5m"));
+        mint_token(&mut context, &mut contract, "2", None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_batch_transfer(
+            vec![
+                (accounts(1), "0".to_string(), None),
+                (accounts(2), "1".to_string(), None),
+                (accounts(1), "2".to_string(), None),
+            ],
+            Some("airdrop".to_string()),
+        );
+        assert_eq!(contract.nft_supply_for_owner(accounts(0)), U128(0));
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(2));
+        assert_eq!(
+            contract.nft_token("1".to_string()).unwrap().owner_id,
+            accounts(2)
+        );
+
+        let logs = test_utils::get_logs();
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].contains(r#""new_owner_id":"bob","token_ids":["0","2"]"#));
+        assert!(logs[0].contains(r#""new_owner_id":"charlie","token_ids":["1"]"#));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is expired")]
+    fn test_batch_transfer_expired_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+        mint_token(&mut context, &mut contract, "1", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(600 * 1_000_000_000)
+            .build());
+        contract.nft_batch_transfer(
+            vec![
+                (accounts(1), "0".to_string(), None),
+                (accounts(1), "1".to_string(), None),
+            ],
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Sender not approved for token 1")]
+    fn test_batch_transfer_not_approved() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+        mint_token(&mut context, &mut contract, "1", None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MIN_REQUIRED_APPROVAL_YOCTO)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve("0".to_string(), accounts(1), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_batch_transfer(
+            vec![
+                (accounts(2), "0".to_string(), Some(1)),
+                (accounts(2), "1".to_string(), None),
+            ],
+            None,
+        );
+    }
+
+    #[test]
+    fn test_update_metadata() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+        let previous_hash = contract.nft_token_metadata_hash("0".to_string()).unwrap();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(5_000_000)
+            .build());
+        let updated_metadata = my_metadata::PrivateMetadata {
+            ciphertext: Base64VecU8::from(b"encrypted Olympus Mons, updated".to_vec()),
+            hash: Base64VecU8::from(env::sha256(b"Olympus Mons, updated")),
+        };
+        let new_hash =
+            contract.nft_update_metadata("0".to_string(), metadata_patch(updated_metadata.clone()));
+        assert_eq!(new_hash, updated_metadata.hash);
+        assert_eq!(
+            contract.nft_token_metadata_hash("0".to_string()),
+            Some(new_hash)
+        );
+        assert_eq!(
+            contract.nft_metadata_history("0".to_string()),
+            vec![previous_hash]
+        );
+        assert!(test_utils::get_logs()[0].contains(r#""event":"nft_metadata_update""#));
+
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.metadata.unwrap().updated_at, Some("5".into()));
+        register_metadata_key(&mut context, &mut contract, accounts(0));
+        assert_eq!(
+            read_token_metadata(&contract, "0", accounts(0)),
+            Some(updated_metadata)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the token creator or an admin can update its metadata")]
+    fn test_update_metadata_not_by_creator() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_update_metadata("0".to_string(), metadata_patch(sample_token_metadata()));
+    }
+
+    #[test]
+    fn test_set_contract_metadata() {
+        use near_contract_standards::non_fungible_token::metadata::NonFungibleTokenMetadataProvider;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.set_contract_name("Renamed".to_string());
+        assert!(test_utils::get_logs()[0].contains(r#""fields":["name"]"#));
+        contract.set_contract_icon(None);
+        contract.set_contract_base_uri(Some("https://example.com/".to_string()));
+
+        let metadata = contract.nft_metadata();
+        assert_eq!(metadata.name, "Renamed");
+        assert_eq!(metadata.icon, None);
+        assert_eq!(metadata.base_uri, Some("https://example.com/".to_string()));
+        assert_eq!(metadata.symbol, "EXAMPLE");
+    }
+
+    #[test]
+    #[should_panic(expected = "Reference and reference hash must be present")]
+    fn test_set_contract_reference_without_hash() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.set_contract_reference(Some("https://example.com/meta.json".to_string()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner can call this method")]
+    fn test_set_contract_name_not_by_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_contract_name("Renamed".to_string());
+    }
+
+    /// State of a version 1 contract with token "0" (expiring after 5 minutes, with a
+    /// royalty) owned by accounts(1).
+    fn v1_state(context: &mut VMContextBuilder) -> my_upgrade::ContractV1 {
+        testing_env!(context
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let mut old = my_upgrade::ContractV1 {
+            tokens: NonFungibleToken::new(
+                StorageKey::NonFungibleToken,
+                accounts(0),
+                Some(StorageKey::TokenMetadata),
+                Some(StorageKey::Enumeration),
+                Some(StorageKey::Approval),
+            ),
+            metadata: LazyOption::new(
+                StorageKey::Metadata,
+                Some(&NFTContractMetadata {
+                    spec: NFT_METADATA_SPEC.to_string(),
+                    name: "Old".to_string(),
+                    symbol: "OLD".to_string(),
+                    icon: None,
+                    base_uri: None,
+                    reference: None,
+                    reference_hash: None,
+                }),
+            ),
+            expiration_timestamp: LookupMap::new(StorageKey::Timestamp),
+            royalty: LookupMap::new(StorageKey::Royalty),
+        };
+        old.tokens.internal_mint(
+            "0".to_string(),
+            accounts(1),
+            Some(TokenMetadata {
+                title: Some("Olympus Mons".into()),
+                description: None,
+                media: None,
+                media_hash: None,
+                copies: None,
+                issued_at: None,
+                expires_at: None,
+                starts_at: None,
+                updated_at: None,
+                extra: None,
+                reference: None,
+                reference_hash: None,
+            }),
+        );
+        old.expiration_timestamp
+            .insert(&"0".to_string(), &(300 * 1_000_000_000));
+        old.royalty
+            .insert(&"0".to_string(), &HashMap::from([(accounts(2), 500)]));
+        old
+    }
+
+    fn write_v1_state(context: &mut VMContextBuilder) {
+        env::state_write(&v1_state(context));
+    }
+
+    #[test]
+    fn test_migrate_from_v1() {
+        use near_contract_standards::non_fungible_token::metadata::NonFungibleTokenMetadataProvider;
+        let mut context = get_context(accounts(0));
+        write_v1_state(&mut context);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), my_upgrade::STATE_VERSION);
+        assert_eq!(contract.nft_metadata().name, "Old");
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.owner_id, accounts(1));
+        assert_eq!(token.expiration_date, Some(300 * 1_000_000_000));
+        assert_eq!(token.royalty, HashMap::from([(accounts(2), 500)]));
+        assert_eq!(token.start_date, None);
+        assert_eq!(token.status, TokenStatus::Active);
+        assert!(!token.in_grace);
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(1));
+        assert_eq!(contract.get_expiry_policy(), None);
+        assert_eq!(contract.get_grace_period(), 0);
+
+        // the migrated state works with the new features and survives another round trip
+        mint_token(&mut context, &mut contract, "1", None);
+        env::state_write(&contract);
+        let contract = Contract::migrate();
+        assert_eq!(contract.nft_total_supply(), U128(2));
+        assert_eq!(contract.nft_max_royalty(), my_royalty::MAX_TOTAL_ROYALTY);
+    }
+
+    #[test]
+    fn test_encrypt_migrated_metadata() {
+        let mut context = get_context(accounts(0));
+        write_v1_state(&mut context);
+        let mut contract = Contract::migrate();
+
+        // views never return the plaintext a migrated token still holds
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.metadata.unwrap().title, None);
+        let plaintext_hash = contract.nft_token_metadata_hash("0".to_string()).unwrap();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_update_metadata("0".to_string(), metadata_patch(sample_token_metadata()));
+        assert_eq!(
+            contract.nft_metadata_history("0".to_string()),
+            vec![plaintext_hash]
+        );
+        let stored = contract
+            .tokens
+            .token_metadata_by_id
+            .as_ref()
+            .unwrap()
+            .get(&"0".to_string())
+            .unwrap();
+        assert_eq!(stored.title, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner can call this method")]
+    fn test_migrate_not_by_owner() {
+        let mut context = get_context(accounts(0));
+        write_v1_state(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        Contract::migrate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner can call this method")]
+    fn test_upgrade_not_by_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.upgrade(vec![0u8; 8].into());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        use crate::my_internal::parse_duration;
+        const SECOND: u64 = 1_000_000_000;
+        const DAY: u64 = 24 * 60 * 60 * SECOND;
+        for (time, expected) in [
+            ("2s", 2 * SECOND),
+            ("5m", 5 * 60 * SECOND),
+            ("12h", 12 * 60 * 60 * SECOND),
+            ("30d", 30 * DAY),
+            ("1d12h30m", DAY + (12 * 60 + 30) * 60 * SECOND),
+            ("30m1d", DAY + 30 * 60 * SECOND),
+            ("2w", 14 * DAY),
+            ("1mo", 30 * DAY),
+            ("1y6mo", 365 * DAY + 180 * DAY),
+            ("0s", 0),
+            ("P1DT12H", DAY + 12 * 60 * 60 * SECOND),
+            ("PT30M", 30 * 60 * SECOND),
+            ("P1M", 30 * DAY),
+            (
+                "P1Y2M3W4DT5H6M7S",
+                365 * DAY + 60 * DAY + 25 * DAY + (5 * 3600 + 6 * 60 + 7) * SECOND,
+            ),
+        ] {
+            assert_eq!(parse_duration(time), Ok(expected), "{}", time);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration_malformed() {
+        use crate::my_internal::parse_duration;
+        for time in [
+            "",
+            "d",
+            "5",
+            "5x",
+            "5 d",
+            "-5s",
+            "1.5h",
+            "1d12",
+            "h12",
+            "P",
+            "PT",
+            "P1DT",
+            "P1H",
+            "PT1D",
+            "P1D1Y",
+            "PT1H1H",
+            "P1.5D",
+            "99999999999999999999s",
+            "600y",
+            "584y584y",
+        ] {
+            assert!(
+                parse_duration(time).is_err(),
+                "{:?} should be rejected",
+                time
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid duration \"\": duration is empty")]
+    fn test_mint_with_empty_expiration_period() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some(""));
+    }
+
+    #[test]
+    #[should_panic(expected = "duration is too long")]
+    fn test_renew_token_overflow() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_renew(
+            "0".to_string(),
+            Some("18446744073709551615s".to_string()),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_mint_with_expires_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        // 2024-01-01T00:00:00Z
+        let now = 1_704_067_200_000 * 1_000_000;
+        for (token_id, expires_at) in [
+            ("0", "2024-01-31T12:00:00Z"),
+            ("1", "2024-01-31T14:00:00+02:00"),
+            ("2", "1706702400000"),
+        ] {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_WITH_DATE_STORAGE_COST)
+                .predecessor_account_id(accounts(0))
+                .block_timestamp(now)
+                .build());
+            let token = contract.nft_mint(
+                Some(token_id.to_string()),
+                accounts(0),
+                sample_token_metadata(),
+                None,
+                None,
+                Some(expires_at.to_string()),
+                None,
+            );
+            assert_eq!(token.expiration_date, Some(1_706_702_400_000 * 1_000_000));
+            assert_eq!(
+                token.metadata.unwrap().expires_at,
+                Some("1706702400000".to_string())
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Expiration date must be in the future")]
+    fn test_mint_with_past_expires_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(1_704_067_200_000 * 1_000_000)
+            .build());
+        contract.nft_mint(
+            Some("0".to_string()),
+            accounts(0),
+            sample_token_metadata(),
+            None,
+            None,
+            Some("2023-12-31T23:59:59Z".to_string()),
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid date \"31/01/2024\"")]
+    fn test_mint_with_malformed_expires_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            Some("0".to_string()),
+            accounts(0),
+            sample_token_metadata(),
+            None,
+            None,
+            Some("31/01/2024".to_string()),
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only one of expiration_period and expires_at can be given")]
+    fn test_mint_with_period_and_expires_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            Some("0".to_string()),
+            accounts(0),
+            sample_token_metadata(),
+            Some("1d".to_string()),
+            None,
+            Some("1706702400000".to_string()),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_renew_token_to_date() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let expiration_date = contract.nft_renew(
+            "0".to_string(),
+            None,
+            Some("1970-01-01T01:00:00Z".to_string()),
+        );
+        assert_eq!(expiration_date, 3600 * 1_000_000_000);
+        assert_eq!(
+            contract
+                .nft_token_extended("0".to_string())
+                .unwrap()
+                .metadata
+                .unwrap()
+                .expires_at,
+            Some("3600000".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "New expiration date must be later than the current one")]
+    fn test_renew_token_to_earlier_date() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_renew("0".to_string(), None, Some("60000".to_string()));
+    }
+
+    fn mint_scheduled_token(context: &mut VMContextBuilder, contract: &mut Contract) {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        // active from the first to the fifth minute
+        contract.nft_mint(
+            Some("0".to_string()),
+            accounts(0),
+            sample_token_metadata(),
+            None,
+            None,
+            Some("300000".to_string()),
+            Some("60000".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_token_status() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_scheduled_token(&mut context, &mut contract);
+
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.status, TokenStatus::Pending);
+        assert_eq!(token.start_date, Some(60 * 1_000_000_000));
+        assert_eq!(
+            contract
+                .nft_token_extended("0".to_string())
+                .unwrap()
+                .metadata
+                .unwrap()
+                .starts_at,
+            Some("60000".to_string())
+        );
+
+        testing_env!(context.block_timestamp(60 * 1_000_000_000).build());
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.status, TokenStatus::Active);
+
+        testing_env!(context.block_timestamp(301 * 1_000_000_000).build());
+        assert_eq!(contract.nft_token_extended("0".to_string()), None);
+        let page = contract.nft_tokens_page(None, None, Some(true));
+        assert_eq!(page.tokens[0].status, TokenStatus::Expired);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is not active yet")]
+    fn test_transfer_pending_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_scheduled_token(&mut context, &mut contract);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(30 * 1_000_000_000)
+            .build());
+        contract.nft_transfer(accounts(1), "0".to_string(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token must start before it expires")]
+    fn test_mint_starting_after_expiration() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_mint(
+            Some("0".to_string()),
+            accounts(0),
+            sample_token_metadata(),
+            Some("1m".to_string()),
+            None,
+            None,
+            Some("2024-01-01T00:00:00Z".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_expiry_policy_burn() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_expiry_policy(Some(my_expiry::ExpiryPolicy::Burn));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        contract.nft_transfer(accounts(1), "0".to_string(), None, None);
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"nep171","version":"1.0.0","event":"nft_burn","data":[{{"owner_id":"{}","token_ids":["0"]}}]}}"#,
+                accounts(0)
+            )]
+        );
+        assert!(!contract.tokens.owner_by_id.contains_key(&"0".to_string()));
+        assert_eq!(contract.nft_total_supply(), U128(0));
+    }
+
+    #[test]
+    fn test_expiry_policy_revert_to_issuer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_expiry_policy(Some(my_expiry::ExpiryPolicy::RevertToIssuer));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_transfer(accounts(1), "0".to_string(), None, None);
+
+        // the token stays with its owner until it is touched
+        testing_env!(context
+            .attached_deposit(0)
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.owner_id, accounts(1));
+        assert_eq!(token.status, TokenStatus::Expired);
+        assert_eq!(contract.nft_tokens(None, None)[0].owner_id, accounts(1));
+        assert_eq!(
+            contract.nft_tokens_for_owner(accounts(1), None, None).len(),
+            1
+        );
+
+        testing_env!(context
+            .attached_deposit(MIN_REQUIRED_APPROVAL_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        assert!(contract
+            .nft_approve("0".to_string(), accounts(2), None)
+            .is_none());
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{{"old_owner_id":"{}","new_owner_id":"{}","token_ids":["0"],"memo":"expired"}}]}}"#,
+                accounts(1),
+                accounts(0)
+            )]
+        );
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.owner_id, accounts(0));
+        assert_eq!(token.expiration_date, None);
+        assert_eq!(token.status, TokenStatus::Active);
+        assert!(token.approved_account_ids.unwrap().is_empty());
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(0));
+        assert_eq!(contract.nft_supply_for_owner(accounts(0)), U128(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is expired")]
+    fn test_expiry_policy_freeze() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_expiry_policy(Some(my_expiry::ExpiryPolicy::Burn));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.set_token_expiry_policy("0".to_string(), Some(my_expiry::ExpiryPolicy::Freeze));
+        assert_eq!(
+            contract.get_token_expiry_policy("0".to_string()),
+            Some(my_expiry::ExpiryPolicy::Freeze)
+        );
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.owner_id, accounts(0));
+        assert_eq!(token.status, TokenStatus::Expired);
+        assert_eq!(
+            contract.nft_tokens_for_owner(accounts(0), None, None).len(),
+            1
+        );
+        assert_eq!(contract.nft_purge_expired(None, 10).burned, 0);
+        assert_eq!(contract.nft_total_supply(), U128(1));
+        assert_eq!(contract.nft_active_supply(Some(accounts(0))), U128(0));
+        contract.nft_transfer(accounts(1), "0".to_string(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires the admin role")]
+    fn test_set_expiry_policy_not_by_admin() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_expiry_policy(Some(my_expiry::ExpiryPolicy::Freeze));
+    }
+
+    #[test]
+    fn test_grace_period() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_grace_period(Some("1d".to_string()));
+        assert_eq!(contract.get_grace_period(), 24 * 60 * 60 * 1_000_000_000);
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .attached_deposit(0)
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.status, TokenStatus::Expired);
+        assert!(token.in_grace);
+        assert_eq!(
+            contract.nft_tokens_for_owner(accounts(0), None, None).len(),
+            1
+        );
+        assert_eq!(contract.nft_purge_expired(None, 10).burned, 0);
+        assert_eq!(contract.nft_active_supply(None), U128(1));
+
+        // extended from now, not from the past expiration date
+        contract.nft_renew("0".to_string(), Some("5m".to_string()), None);
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.expiration_date, Some(11 * 60 * 1_000_000_000));
+        assert_eq!(token.status, TokenStatus::Active);
+        assert!(!token.in_grace);
+    }
+
+    #[test]
+    fn test_token_grace_period() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_expiry_policy(Some(my_expiry::ExpiryPolicy::Burn));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.set_token_grace_period("0".to_string(), Some("1h".to_string()));
+        assert_eq!(
+            contract.get_token_grace_period("0".to_string()),
+            60 * 60 * 1_000_000_000
+        );
+
+        // the expiry policy waits for the end of the grace period
+        testing_env!(context
+            .attached_deposit(MIN_REQUIRED_APPROVAL_YOCTO)
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        assert!(
+            contract
+                .nft_token_extended("0".to_string())
+                .unwrap()
+                .in_grace
+        );
+
+        testing_env!(context.block_timestamp(66 * 60 * 1_000_000_000).build());
+        assert_eq!(contract.nft_token("0".to_string()), None);
+        assert!(contract
+            .nft_approve("0".to_string(), accounts(1), None)
+            .is_none());
+        assert!(!contract.tokens.owner_by_id.contains_key(&"0".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is in its grace period and can only be renewed")]
+    fn test_transfer_token_in_grace() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_grace_period(Some("1d".to_string()));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        contract.nft_transfer(accounts(1), "0".to_string(), None, None);
+    }
+
+    #[test]
+    fn test_mint_builds_public_metadata() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(7_000_000)
+            .build());
+        let token = contract.nft_mint(
+            Some("0".to_string()),
+            accounts(0),
+            sample_token_metadata(),
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_eq!(token.metadata, Some(public_token_metadata("7", None)));
+    }
+
+    #[test]
+    fn test_batch_mint_with_expires_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_WITH_DATE_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        let mut entry = batch_entry("0", accounts(1));
+        entry.3 = None;
+        entry.5 = Some("1706702400000".to_string());
+        let minted = contract.nft_batch_mint(vec![entry]);
+        assert_eq!(
+            minted[0].expiration_date,
+            Some(1_706_702_400_000 * 1_000_000)
+        );
+        assert_eq!(
+            minted[0].metadata.as_ref().unwrap().expires_at,
+            Some("1706702400000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_batch_mint_with_starts_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_WITH_DATE_STORAGE_COST * 2)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        let mut entry = batch_entry("0", accounts(1));
+        entry.6 = Some("60000".to_string());
+        let minted = contract.nft_batch_mint(vec![entry]);
+        assert_eq!(minted[0].status, TokenStatus::Pending);
+        assert_eq!(minted[0].start_date, Some(60 * 1_000_000_000));
+        assert_eq!(
+            minted[0].metadata.as_ref().unwrap().starts_at,
+            Some("60000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sale_with_token_starts_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        start_sale(&mut context, &mut contract, None, None);
+        let mut sale = contract.get_sale().unwrap();
+        sale.token_starts_at = Some("60000".to_string());
+        contract.set_sale(sale);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(50)
+            .build());
+        let tokens = contract.nft_mint_public(accounts(1), 1, None);
+        assert_eq!(tokens[0].status, TokenStatus::Pending);
+        assert_eq!(tokens[0].start_date, Some(60 * 1_000_000_000));
+    }
+
+    #[test]
+    fn test_purge_reverts_sale_token_to_contract() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_expiry_policy(Some(my_expiry::ExpiryPolicy::RevertToIssuer));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .block_timestamp(0)
+            .build());
+        contract.set_sale(my_sale::SaleConfig {
+            price: U128(ONE_NEAR),
+            max_supply: 1,
+            per_account_limit: None,
+            starts_at: None,
+            ends_at: None,
+            allowlist: None,
+            treasury_id: accounts(4),
+            token_metadata: sample_token_metadata(),
+            expiration_period: Some("5m".to_string()),
+            token_expires_at: None,
+            token_starts_at: None,
+        });
+        buy_tokens(&mut context, &mut contract, accounts(1), 1, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_transfer(accounts(2), "0".to_string(), None, None);
+
+        testing_env!(context
+            .attached_deposit(0)
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        let purged = contract.nft_purge_expired(None, 10);
+        assert_eq!(purged.burned, 0);
+        assert_eq!(purged.reverted, 1);
+        // the buyer paid for the mint, but the token was issued by the contract
+        let token = contract.nft_token_extended("0".to_string()).unwrap();
+        assert_eq!(token.owner_id, env::current_account_id());
+        assert_eq!(token.status, TokenStatus::Active);
+        assert_eq!(
+            contract.nft_supply_for_owner(env::current_account_id()),
+            U128(1)
+        );
+        assert_eq!(contract.nft_active_supply(None), U128(1));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "New expiration date must be later than the current one and than now"
+    )]
+    fn test_renew_in_grace_to_past_date() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_grace_period(Some("1d".to_string()));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .attached_deposit(0)
+            .block_timestamp(10 * 60 * 1_000_000_000)
+            .build());
+        // later than the expiration date, but already over
+        contract.nft_renew("0".to_string(), None, Some("360000".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is paused")]
+    fn test_renew_when_paused() {
+        use crate::my_roles::Role;
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.grant_role(accounts(3), Role::Pauser);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.pause();
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.nft_renew("0".to_string(), Some("5m".to_string()), None);
+    }
+
+    #[test]
+    fn test_active_supply_after_renewing_frozen_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_expiry_policy(Some(my_expiry::ExpiryPolicy::Freeze));
+        mint_token(&mut context, &mut contract, "0", Some("5m"));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .block_timestamp(6 * 60 * 1_000_000_000)
+            .build());
+        contract.nft_purge_expired(None, 10);
+        assert_eq!(contract.nft_active_supply(None), U128(0));
+
+        // the grace period brings the token back within reach of a renewal
+        contract.set_grace_period(Some("1d".to_string()));
+        contract.nft_renew("0".to_string(), Some("5m".to_string()), None);
+        assert_eq!(contract.nft_active_supply(None), U128(1));
+        assert_eq!(contract.nft_active_supply(Some(accounts(0))), U128(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        contract.nft_transfer(accounts(1), "0".to_string(), None, None);
+        assert_eq!(contract.nft_active_supply(None), U128(1));
+        assert_eq!(contract.nft_active_supply(Some(accounts(0))), U128(0));
+        assert_eq!(contract.nft_active_supply(Some(accounts(1))), U128(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the token creator or an admin can update its metadata")]
+    fn test_update_metadata_by_sale_buyer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        start_sale(&mut context, &mut contract, None, None);
+        buy_tokens(&mut context, &mut contract, accounts(1), 1, None);
+
+        // the buyer paid for the mint, but didn't create the token
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_update_metadata("0".to_string(), metadata_patch(sample_token_metadata()));
+    }
+
+    #[test]
+    fn test_update_metadata_issued_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        mint_token(&mut context, &mut contract, "0", None);
+        let hash = contract.nft_token_metadata_hash("0".to_string()).unwrap();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(5_000_000)
+            .build());
+        let new_hash = contract.nft_update_metadata(
+            "0".to_string(),
+            my_metadata::TokenMetadataPatch {
+                metadata: None,
+                issued_at: Some("2024-01-31T12:00:00Z".to_string()),
+            },
+        );
+        // the encrypted metadata is kept
+        assert_eq!(new_hash, hash);
+        let metadata = contract
+            .nft_token_extended("0".to_string())
+            .unwrap()
+            .metadata
+            .unwrap();
+        assert_eq!(metadata.issued_at, Some("1706702400000".to_string()));
+        assert_eq!(metadata.updated_at, Some("5".to_string()));
+        register_metadata_key(&mut context, &mut contract, accounts(0));
+        assert_eq!(
+            read_token_metadata(&contract, "0", accounts(0)),
+            Some(sample_token_metadata())
+        );
+    }
 }
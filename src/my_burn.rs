@@ -0,0 +1,183 @@
+use near_contract_standards::non_fungible_token::{
+    bytes_for_approved_account_id, events::NftBurn, refund_approved_account_ids,
+};
+use near_sdk::{assert_one_yocto, near_bindgen, require, Balance};
+
+use crate::my_expiry::ExpiryPolicy;
+use crate::*;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PurgeResult {
+    /// Number of tokens burned by the call.
+    pub burned: u64,
+    /// Number of tokens reverted to their issuer by the call.
+    pub reverted: u64,
+    /// Pass as `from_token_id` to purge the next tokens. `None` when the last token of the
+    /// collection has been looked at.
+    pub next_token_id: Option<TokenId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Burns `token_id`. Can be called by the token owner or by an approved account; the
+    /// storage released by the token is refunded to the caller, except for the storage of
+    /// its approvals, which is refunded to the token owner.
+    #[payable]
+    pub fn nft_burn(&mut self, token_id: TokenId, memo: Option<String>) {
+        assert_one_yocto();
+        self.assert_not_paused();
+        let owner_id = self.tokens.owner_by_id.get(&token_id);
+        require!(owner_id.is_some(), "Token not found");
+        let owner_id = owner_id.unwrap();
+
+        let predecessor_id = env::predecessor_account_id();
+        if predecessor_id != owner_id {
+            let approved = self
+                .tokens
+                .approvals_by_id
+                .as_ref()
+                .and_then(|by_id| by_id.get(&token_id))
+                .map(|approved_account_ids| approved_account_ids.contains_key(&predecessor_id))
+                .unwrap_or(false);
+            require!(
+                approved,
+                "Only the token owner or an approved account can burn the token"
+            );
+        }
+
+        let initial_storage_usage = env::storage_usage();
+        let (_, approved_account_ids) = self.internal_burn(&token_id);
+        let mut storage_released = initial_storage_usage - env::storage_usage();
+        // the approvals were paid for by the owner, so their storage goes back to the owner
+        if let Some(approved_account_ids) = approved_account_ids {
+            storage_released = storage_released.saturating_sub(
+                approved_account_ids
+                    .keys()
+                    .map(bytes_for_approved_account_id)
+                    .sum(),
+            );
+            refund_approved_account_ids(owner_id.clone(), &approved_account_ids);
+        }
+        Promise::new(predecessor_id.clone())
+            .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+
+        NftBurn {
+            owner_id: &owner_id,
+            token_ids: &[&token_id],
+            authorized_id: Some(&predecessor_id).filter(|id| **id != owner_id),
+            memo: memo.as_deref(),
+        }
+        .emit();
+    }
+
+    /// Looks at up to `limit` tokens with ids greater than `from_token_id`, in token id
+    /// order, and burns the expired ones. The storage of each token is refunded to the
+    /// account that paid for its mint, except for the storage of its approvals, which is
+    /// refunded to the token owner. Expired tokens whose expiry policy is
+    /// `revert_to_issuer` are reverted instead, and frozen ones are only taken out of
+    /// `nft_active_supply`. Pass the returned `next_token_id` as `from_token_id` to go on.
+    pub fn nft_purge_expired(&mut self, from_token_id: Option<TokenId>, limit: u64) -> PurgeResult {
+        require!(limit != 0, "Cannot provide limit of 0.");
+        let scanned: Vec<(TokenId, AccountId)> = match from_token_id {
+            Some(from_token_id) => self
+                .tokens
+                .owner_by_id
+                .iter_from(from_token_id)
+                .take(limit as usize)
+                .collect(),
+            None => self
+                .tokens
+                .owner_by_id
+                .iter()
+                .take(limit as usize)
+                .collect(),
+        };
+        let next_token_id = if scanned.len() as u64 == limit {
+            scanned.last().map(|(token_id, _)| token_id.clone())
+        } else {
+            None
+        };
+        let mut expired = Vec::new();
+        let mut reverted = 0;
+        for (token_id, owner_id) in scanned {
+            if !self.token_is_expired(&token_id) {
+                continue;
+            }
+            match self.token_expiry_policy(&token_id) {
+                Some(ExpiryPolicy::Burn) | None => expired.push(token_id),
+                Some(ExpiryPolicy::RevertToIssuer) => {
+                    self.internal_revert_to_issuer(&token_id);
+                    reverted += 1;
+                }
+                Some(ExpiryPolicy::Freeze) => self.internal_mark_expired(&token_id, &owner_id),
+            }
+        }
+
+        PurgeResult {
+            burned: self.internal_burn_expired(expired),
+            reverted,
+            next_token_id,
+        }
+    }
+}
+
+impl Contract {
+    /// Burns `token_ids`, refunding their storage like `nft_purge_expired` does, and logs
+    /// the `nft_burn` events. Returns how many tokens were burned.
+    pub(crate) fn internal_burn_expired(&mut self, token_ids: Vec<TokenId>) -> u64 {
+        let mut burned: Vec<(AccountId, Vec<TokenId>)> = Vec::new();
+        let mut refunds: HashMap<AccountId, Balance> = HashMap::new();
+        for token_id in token_ids {
+            let payer_id = self
+                .minted_by
+                .get(&token_id)
+                .unwrap_or_else(env::current_account_id);
+            let initial_storage_usage = env::storage_usage();
+            let (owner_id, approved_account_ids) = self.internal_burn(&token_id);
+            let mut storage_released = initial_storage_usage - env::storage_usage();
+
+            if let Some(approved_account_ids) = approved_account_ids {
+                storage_released = storage_released.saturating_sub(
+                    approved_account_ids
+                        .keys()
+                        .map(bytes_for_approved_account_id)
+                        .sum(),
+                );
+                refund_approved_account_ids(owner_id.clone(), &approved_account_ids);
+            }
+            *refunds.entry(payer_id).or_default() +=
+                Balance::from(storage_released) * env::storage_byte_cost();
+
+            match burned.iter_mut().find(|(id, _)| *id == owner_id) {
+                Some((_, token_ids)) => token_ids.push(token_id),
+                None => burned.push((owner_id, vec![token_id])),
+            }
+        }
+
+        for (payer_id, amount) in refunds {
+            if payer_id != env::current_account_id() && amount > 0 {
+                Promise::new(payer_id).transfer(amount);
+            }
+        }
+
+        let token_ids: Vec<Vec<&str>> = burned
+            .iter()
+            .map(|(_, token_ids)| token_ids.iter().map(|id| id.as_str()).collect())
+            .collect();
+        let events: Vec<NftBurn> = burned
+            .iter()
+            .zip(token_ids.iter())
+            .map(|((owner_id, _), token_ids)| NftBurn {
+                owner_id,
+                token_ids,
+                authorized_id: None,
+                memo: None,
+            })
+            .collect();
+        if !events.is_empty() {
+            NftBurn::emit_many(&events);
+        }
+        token_ids.iter().map(|ids| ids.len() as u64).sum()
+    }
+}
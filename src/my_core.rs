@@ -1,25 +1,41 @@
 use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
-use near_sdk::{env::log_str, require, PromiseOrValue};
+use near_contract_standards::non_fungible_token::events::NftTransfer;
+use near_contract_standards::non_fungible_token::Token;
+use near_sdk::{assert_one_yocto, env::log_str, require, PromiseOrValue};
+use std::collections::HashSet;
 
 use crate::*;
 
-// impl NonFungibleTokenCore for Contract {
+/// Maximum number of tokens moved by one `nft_batch_transfer` call.
+pub const MAX_BATCH_TRANSFER_SIZE: usize = 50;
+
+/// `(receiver_id, token_id, approval_id)`
+pub type BatchTransferEntry = (AccountId, TokenId, Option<u64>);
+
 #[near_bindgen]
-impl Contract {
+impl NonFungibleTokenCore for Contract {
     #[payable]
-    pub fn nft_transfer(
+    fn nft_transfer(
         &mut self,
         receiver_id: AccountId,
         token_id: TokenId,
         approval_id: Option<u64>,
         memo: Option<String>,
     ) {
+        self.assert_not_paused();
+        if self.internal_apply_expiry_policy(&token_id) {
+            return;
+        }
+        self.assert_token_active(&token_id);
+        if let Some(owner_id) = self.tokens.owner_by_id.get(&token_id) {
+            self.internal_move_expired(&token_id, &owner_id, &receiver_id);
+        }
         self.tokens
             .nft_transfer(receiver_id, token_id, approval_id, memo)
     }
 
     #[payable]
-    pub fn nft_transfer_call(
+    fn nft_transfer_call(
         &mut self,
         receiver_id: AccountId,
         token_id: TokenId,
@@ -27,40 +43,124 @@ impl Contract {
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<bool> {
+        self.assert_not_paused();
+        if self.internal_apply_expiry_policy(&token_id) {
+            return PromiseOrValue::Value(false);
+        }
+        self.assert_token_active(&token_id);
+        if let Some(owner_id) = self.tokens.owner_by_id.get(&token_id) {
+            self.internal_move_expired(&token_id, &owner_id, &receiver_id);
+        }
         self.tokens
             .nft_transfer_call(receiver_id, token_id, approval_id, memo, msg)
     }
 
-    pub fn nft_token(&self, token_id: TokenId) -> Option<JsonToken> {
-        if !self.tokens.owner_by_id.contains_key(&token_id) {
-            return None;
-        }
-        if env::predecessor_account_id() != env::current_account_id()
-            && !self.token_is_not_expired(&token_id)
-        {
-            log_str(&format!("Token is expired."));
-            return None;
+    /// Views only return the public part of the token metadata, the dates maintained by
+    /// the contract. The rest is stored encrypted and read with `nft_token_metadata`.
+    fn nft_token(&self, token_id: TokenId) -> Option<Token> {
+        self.nft_token_extended(token_id).map(Token::from)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Transfers every token of `transfers` like `nft_transfer` does. Either all of them are
+    /// transferred or none is; a single `nft_transfer` event is logged for the whole batch.
+    #[payable]
+    pub fn nft_batch_transfer(&mut self, transfers: Vec<BatchTransferEntry>, memo: Option<String>) {
+        assert_one_yocto();
+        self.assert_not_paused();
+        require!(!transfers.is_empty(), "Nothing to transfer");
+        require!(
+            transfers.len() <= MAX_BATCH_TRANSFER_SIZE,
+            format!(
+                "Cannot transfer more than {} tokens at once",
+                MAX_BATCH_TRANSFER_SIZE
+            )
+        );
+
+        let sender_id = env::predecessor_account_id();
+        let mut token_ids = HashSet::new();
+        // (old owner, new owner, authorized id, token ids), in the order of the batch
+        let mut moves: Vec<(AccountId, AccountId, Option<AccountId>, Vec<TokenId>)> = Vec::new();
+        for (receiver_id, token_id, approval_id) in transfers {
+            require!(
+                token_ids.insert(token_id.clone()),
+                format!("Token {} is transferred more than once", token_id)
+            );
+            let owner_id = self.tokens.owner_by_id.get(&token_id);
+            require!(owner_id.is_some(), format!("Token {} not found", token_id));
+            let owner_id = owner_id.unwrap();
+            self.assert_token_active(&token_id);
+
+            // approvals are cleared by the transfer, same as in `nft_transfer`
+            let approved_account_ids = self
+                .tokens
+                .approvals_by_id
+                .as_mut()
+                .and_then(|by_id| by_id.remove(&token_id));
+            let authorized_id = if sender_id != owner_id {
+                let actual_approval_id = approved_account_ids
+                    .as_ref()
+                    .and_then(|approvals| approvals.get(&sender_id));
+                require!(
+                    actual_approval_id.is_some(),
+                    format!("Sender not approved for token {}", token_id)
+                );
+                require!(
+                    approval_id.is_none() || actual_approval_id == approval_id.as_ref(),
+                    format!("Wrong approval_id for token {}", token_id)
+                );
+                Some(sender_id.clone())
+            } else {
+                None
+            };
+            require!(
+                owner_id != receiver_id,
+                "Current and next owner must differ"
+            );
+
+            self.tokens
+                .internal_transfer_unguarded(&token_id, &owner_id, &receiver_id);
+            self.internal_move_expired(&token_id, &owner_id, &receiver_id);
+            match moves.iter_mut().find(|(old, new, authorized, _)| {
+                *old == owner_id && *new == receiver_id && *authorized == authorized_id
+            }) {
+                Some((_, _, _, token_ids)) => token_ids.push(token_id),
+                None => moves.push((owner_id, receiver_id, authorized_id, vec![token_id])),
+            }
         }
-        if self.tokens.owner_by_id.get(&token_id).unwrap() == env::predecessor_account_id()
-            || env::predecessor_account_id() == env::current_account_id()
-        {
-            self.tokens.nft_token(token_id).map(|token| JsonToken {
-                expiration_date: self.expiration_timestamp.get(&token.token_id),
-                royalty: self.royalty.get(&token.token_id).unwrap(),
-                token_id: token.token_id,
-                owner_id: token.owner_id,
-                metadata: token.metadata,
-                approved_account_ids: token.approved_account_ids,
-            })
-        } else {
-            self.tokens.nft_token(token_id).map(|token| JsonToken {
-                expiration_date: self.expiration_timestamp.get(&token.token_id),
-                royalty: self.royalty.get(&token.token_id).unwrap(),
-                token_id: token.token_id,
-                owner_id: token.owner_id,
-                metadata: None,
-                approved_account_ids: token.approved_account_ids,
-            })
+
+        let token_ids: Vec<Vec<&str>> = moves
+            .iter()
+            .map(|(_, _, _, token_ids)| token_ids.iter().map(String::as_str).collect())
+            .collect();
+        let events: Vec<NftTransfer> = moves
+            .iter()
+            .zip(token_ids.iter())
+            .map(
+                |((old_owner_id, new_owner_id, authorized_id, _), token_ids)| NftTransfer {
+                    old_owner_id,
+                    new_owner_id,
+                    token_ids,
+                    authorized_id: authorized_id.as_ref(),
+                    memo: memo.as_deref(),
+                },
+            )
+            .collect();
+        NftTransfer::emit_many(&events);
+    }
+
+    /// Same as `nft_token`, plus the expiration date and the royalty of the token. Expired
+    /// tokens are shown according to their expiry policy.
+    pub fn nft_token_extended(&self, token_id: TokenId) -> Option<JsonToken> {
+        let owner_id = self.tokens.owner_by_id.get(&token_id)?;
+        match self.token_view_owner(&token_id, owner_id) {
+            Some(owner_id) => Some(self.enum_get_token(owner_id, token_id)),
+            None => {
+                log_str("Token is expired.");
+                None
+            }
         }
     }
 }
@@ -1,11 +1,37 @@
+use near_contract_standards::non_fungible_token::enumeration::NonFungibleTokenEnumeration;
+use near_contract_standards::non_fungible_token::Token;
 use near_sdk::{json_types::U128, near_bindgen, require};
 
 use crate::*;
 
-// impl NonFungibleTokenEnumeration for Contract {
+/// Maximum number of tokens looked at by one `nft_tokens_page` or
+/// `nft_tokens_for_owner_page` call.
+pub const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenPage {
+    pub tokens: Vec<JsonToken>,
+    /// Id of the last token looked at, to pass as `from_token_id` to get the next page.
+    /// `None` when there are no more tokens.
+    pub next_token_id: Option<TokenId>,
+}
+
 #[near_bindgen]
-impl Contract {
-    pub fn nft_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<JsonToken> {
+impl NonFungibleTokenEnumeration for Contract {
+    /// `from_index` and `limit` address positions among all stored tokens, hidden ones
+    /// included, so a page may contain fewer than `limit` tokens but the next page always
+    /// starts at `from_index + limit`. See `nft_tokens_page` for cursor-based pagination.
+    ///
+    /// Both supply views are O(1): they read the lengths kept by `owner_by_id` and
+    /// `tokens_per_owner`, which mint, transfer, burn and `nft_purge_expired` update. Expired
+    /// tokens are counted until they are purged; see `nft_active_supply` for the tokens that
+    /// haven't expired.
+    fn nft_total_supply(&self) -> U128 {
+        self.tokens.nft_total_supply()
+    }
+
+    fn nft_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token> {
         let start_index: u128 = from_index.map(From::from).unwrap_or_default();
         require!(
             (self.tokens.owner_by_id.len() as u128) >= start_index,
@@ -16,19 +42,27 @@ impl Contract {
         self.tokens
             .owner_by_id
             .iter()
-            .filter(|(token_id, _)| self.token_is_not_expired(token_id))
             .skip(start_index as usize)
             .take(limit)
-            .map(|(token_id, owner_id)| self.enum_get_token(owner_id, token_id))
+            .filter_map(|(token_id, owner_id)| {
+                self.token_view_owner(&token_id, owner_id)
+                    .map(|owner_id| (token_id, owner_id))
+            })
+            .map(|(token_id, owner_id)| self.enum_get_token(owner_id, token_id).into())
             .collect()
     }
 
-    pub fn nft_tokens_for_owner(
+    fn nft_supply_for_owner(&self, account_id: AccountId) -> U128 {
+        self.tokens.nft_supply_for_owner(account_id)
+    }
+
+    /// Same as `nft_tokens`, for the tokens of `account_id`.
+    fn nft_tokens_for_owner(
         &self,
         account_id: AccountId,
         from_index: Option<U128>,
         limit: Option<u64>,
-    ) -> Vec<JsonToken> {
+    ) -> Vec<Token> {
         let tokens_per_owner = self.tokens.tokens_per_owner.as_ref().unwrap_or_else(|| {
             env::panic_str(
                 "Could not find tokens_per_owner when calling a method on the \
@@ -49,10 +83,148 @@ impl Contract {
         );
         token_set
             .iter()
-            .filter(|token_id| self.token_is_not_expired(token_id))
             .skip(start_index as usize)
             .take(limit)
-            .map(|token_id| self.enum_get_token(account_id.clone(), token_id))
+            .filter(|token_id| self.token_is_listed_for(token_id, &account_id))
+            .map(|token_id| self.enum_get_token(account_id.clone(), token_id).into())
             .collect()
     }
 }
+
+#[near_bindgen]
+impl Contract {
+    /// Cursor-based alternative to `nft_tokens`: looks at up to `limit` tokens (at most
+    /// `MAX_PAGE_SIZE`, which is also the default) with ids greater than `from_token_id`, in
+    /// token id order, and returns the ones shown. Pages don't shift when tokens expire or
+    /// get burned. Expired tokens hidden by their expiry policy are left out unless
+    /// `include_expired` is set, which also shows every token under its current owner.
+    pub fn nft_tokens_page(
+        &self,
+        from_token_id: Option<TokenId>,
+        limit: Option<u64>,
+        include_expired: Option<bool>,
+    ) -> TokenPage {
+        let limit = page_limit(limit);
+        let include_expired = include_expired.unwrap_or(false);
+        let view_owner = |token_id: &TokenId, owner_id: AccountId| {
+            if include_expired {
+                Some(owner_id)
+            } else {
+                self.token_view_owner(token_id, owner_id)
+            }
+        };
+        match from_token_id {
+            Some(from_token_id) => self.tokens_page(
+                self.tokens.owner_by_id.iter_from(from_token_id),
+                limit,
+                view_owner,
+            ),
+            None => self.tokens_page(self.tokens.owner_by_id.iter(), limit, view_owner),
+        }
+    }
+
+    /// Same as `nft_tokens_page`, for the tokens of `account_id`. Only `limit` tokens are
+    /// read, but the ids of all the tokens of the owner are loaded and sorted on every
+    /// call, so its cost grows with the number of tokens they own.
+    pub fn nft_tokens_for_owner_page(
+        &self,
+        account_id: AccountId,
+        from_token_id: Option<TokenId>,
+        limit: Option<u64>,
+        include_expired: Option<bool>,
+    ) -> TokenPage {
+        let limit = page_limit(limit);
+        let mut token_ids: Vec<TokenId> = self
+            .tokens
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|tokens_per_owner| tokens_per_owner.get(&account_id))
+            .map(|token_set| token_set.to_vec())
+            .unwrap_or_default();
+        token_ids.sort();
+        let include_expired = include_expired.unwrap_or(false);
+        self.tokens_page(
+            token_ids
+                .into_iter()
+                .filter(|token_id| from_token_id.as_ref().is_none_or(|from| token_id > from))
+                .map(|token_id| (token_id, account_id.clone())),
+            limit,
+            |token_id, owner_id| {
+                (include_expired || self.token_is_listed_for(token_id, &owner_id))
+                    .then_some(owner_id)
+            },
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Number of tokens that haven't expired (pending ones included), for `account_id` if
+    /// given or for the whole collection otherwise. Like the other supply views this is
+    /// O(1), so expired tokens only leave the count once `nft_purge_expired` looks at
+    /// them: it burns them, reverts them to their issuer (after which they count again) or,
+    /// if they are frozen, just takes them out of the count until they are renewed.
+    pub fn nft_active_supply(&self, account_id: Option<AccountId>) -> U128 {
+        match account_id {
+            Some(account_id) => U128(
+                self.tokens
+                    .nft_supply_for_owner(account_id.clone())
+                    .0
+                    .saturating_sub(u128::from(
+                        self.expired_supply_per_owner
+                            .get(&account_id)
+                            .unwrap_or_default(),
+                    )),
+            ),
+            None => U128(
+                self.tokens
+                    .nft_total_supply()
+                    .0
+                    .saturating_sub(u128::from(self.expired_supply)),
+            ),
+        }
+    }
+}
+
+impl Contract {
+    /// `token_id`, owned by `account_id`, is shown in the listings of `account_id`: it is
+    /// neither hidden nor shown as owned by its issuer because it has expired.
+    fn token_is_listed_for(&self, token_id: &TokenId, account_id: &AccountId) -> bool {
+        self.token_view_owner(token_id, account_id.clone()).as_ref() == Some(account_id)
+    }
+
+    /// Builds a page from up to `limit` tokens of `tokens`, keeping the ones `view_owner`
+    /// shows (under the owner it returns). The cursor is the last token looked at, shown
+    /// or not, so the work done by one call is bounded by `limit`.
+    fn tokens_page(
+        &self,
+        tokens: impl Iterator<Item = (TokenId, AccountId)>,
+        limit: usize,
+        view_owner: impl Fn(&TokenId, AccountId) -> Option<AccountId>,
+    ) -> TokenPage {
+        let mut page = Vec::new();
+        let mut scanned = 0;
+        let mut last_token_id = None;
+        for (token_id, owner_id) in tokens.take(limit) {
+            scanned += 1;
+            if let Some(owner_id) = view_owner(&token_id, owner_id) {
+                page.push(self.enum_get_token(owner_id, token_id.clone()));
+            }
+            last_token_id = Some(token_id);
+        }
+        TokenPage {
+            tokens: page,
+            next_token_id: if scanned == limit {
+                last_token_id
+            } else {
+                None
+            },
+        }
+    }
+}
+
+fn page_limit(limit: Option<u64>) -> usize {
+    let limit = limit.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    require!(limit != 0, "Cannot provide limit of 0.");
+    limit as usize
+}
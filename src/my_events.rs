@@ -0,0 +1,174 @@
+//! NEP-297 events for the extensions of this contract that are not covered by nep171.
+//!
+//! The format follows `near_contract_standards::non_fungible_token::events`: every event is
+//! logged as `EVENT_JSON:{"standard":"nft_factory","version":"1.0.0","event":...,"data":[...]}`.
+
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, serde_json, AccountId};
+
+const EVENT_STANDARD: &str = "nft_factory";
+const EVENT_VERSION: &str = "1.0.0";
+
+/// Data to log when the expiration date of a token is pushed forward.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftRenew<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_id: &'a str,
+    pub expiration_date: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorized_id: Option<&'a AccountId>,
+}
+
+impl NftRenew<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[NftRenew<'_>]) {
+        FactoryEvent::new(FactoryEventKind::NftRenew(data)).emit()
+    }
+}
+
+/// Data to log when a royalty holder moves part of their royalty to another account.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftRoyaltyTransfer<'a> {
+    pub token_id: &'a str,
+    pub old_holder_id: &'a AccountId,
+    pub new_holder_id: &'a AccountId,
+    pub basis_points: u32,
+}
+
+impl NftRoyaltyTransfer<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[NftRoyaltyTransfer<'_>]) {
+        FactoryEvent::new(FactoryEventKind::NftRoyaltyTransfer(data)).emit()
+    }
+}
+
+/// Data to log when a role is granted to an account.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoleGranted<'a> {
+    pub account_id: &'a AccountId,
+    pub role: &'a str,
+    pub authorized_id: &'a AccountId,
+}
+
+impl RoleGranted<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[RoleGranted<'_>]) {
+        FactoryEvent::new(FactoryEventKind::RoleGranted(data)).emit()
+    }
+}
+
+/// Data to log when a role is revoked from an account.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoleRevoked<'a> {
+    pub account_id: &'a AccountId,
+    pub role: &'a str,
+    pub authorized_id: &'a AccountId,
+}
+
+impl RoleRevoked<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[RoleRevoked<'_>]) {
+        FactoryEvent::new(FactoryEventKind::RoleRevoked(data)).emit()
+    }
+}
+
+/// Data to log when the metadata of a token is updated. Only hashes are logged, since the
+/// metadata itself isn't public.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftMetadataUpdate<'a> {
+    pub token_id: &'a str,
+    pub previous_hash: &'a Base64VecU8,
+    pub new_hash: &'a Base64VecU8,
+    pub authorized_id: &'a AccountId,
+}
+
+impl NftMetadataUpdate<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[NftMetadataUpdate<'_>]) {
+        FactoryEvent::new(FactoryEventKind::NftMetadataUpdate(data)).emit()
+    }
+}
+
+/// Data to log when the metadata of the collection is changed.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractMetadataUpdate<'a> {
+    pub fields: &'a [&'a str],
+    pub authorized_id: &'a AccountId,
+}
+
+impl ContractMetadataUpdate<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[ContractMetadataUpdate<'_>]) {
+        FactoryEvent::new(FactoryEventKind::ContractMetadataUpdate(data)).emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+struct FactoryEvent<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: FactoryEventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum FactoryEventKind<'a> {
+    NftRenew(&'a [NftRenew<'a>]),
+    NftRoyaltyTransfer(&'a [NftRoyaltyTransfer<'a>]),
+    RoleGranted(&'a [RoleGranted<'a>]),
+    RoleRevoked(&'a [RoleRevoked<'a>]),
+    NftMetadataUpdate(&'a [NftMetadataUpdate<'a>]),
+    ContractMetadataUpdate(&'a [ContractMetadataUpdate<'a>]),
+}
+
+impl<'a> FactoryEvent<'a> {
+    fn new(event_kind: FactoryEventKind<'a>) -> Self {
+        Self {
+            standard: EVENT_STANDARD,
+            version: EVENT_VERSION,
+            event_kind,
+        }
+    }
+
+    fn emit(self) {
+        env::log_str(&format!(
+            "EVENT_JSON:{}",
+            serde_json::to_string(&self).unwrap_or_else(|_| env::abort())
+        ));
+    }
+}
@@ -0,0 +1,188 @@
+use near_contract_standards::non_fungible_token::events::NftTransfer;
+use near_contract_standards::non_fungible_token::{refund_approved_account_ids, refund_deposit};
+use near_sdk::serde::Deserialize;
+use near_sdk::{near_bindgen, require};
+
+use crate::my_internal::parse_time;
+use crate::my_roles::Role;
+use crate::*;
+
+/// What happens to a token once it has expired and its grace period is over. Policies are
+/// applied lazily: nothing changes at the expiration date itself, the policy kicks in the
+/// next time the token is transferred or approved, or when `nft_purge_expired` reaches it.
+/// Without a policy an expired token is hidden from views, can't be transferred and is
+/// burned by `nft_purge_expired`.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryPolicy {
+    /// The token is burned, its storage refunded like in `nft_purge_expired`.
+    Burn,
+    /// The token goes back to its issuer, and loses its expiration date. The issuer is the
+    /// account that minted the token, or the contract account for tokens bought in the sale
+    /// or when the minter is the owner. Until then the token stays visible with its owner.
+    RevertToIssuer,
+    /// The token stays with its owner and visible, but can't be transferred or approved.
+    Freeze,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the expiry policy of the collection; `None` restores the default behavior.
+    /// Tokens with their own policy keep it.
+    pub fn set_expiry_policy(&mut self, policy: Option<ExpiryPolicy>) {
+        self.assert_role(Role::Admin);
+        self.expiry_policy = policy;
+    }
+
+    pub fn get_expiry_policy(&self) -> Option<ExpiryPolicy> {
+        self.expiry_policy
+    }
+
+    /// Overrides the expiry policy of the collection for `token_id`; `None` removes the
+    /// override.
+    #[payable]
+    pub fn set_token_expiry_policy(&mut self, token_id: TokenId, policy: Option<ExpiryPolicy>) {
+        self.assert_role(Role::Admin);
+        require!(
+            self.tokens.owner_by_id.contains_key(&token_id),
+            "Token not found"
+        );
+        let initial_storage_usage = env::storage_usage();
+        match policy {
+            Some(policy) => self.token_expiry_policies.insert(&token_id, &policy),
+            None => self.token_expiry_policies.remove(&token_id),
+        };
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Expiry policy that applies to `token_id`: its own one if set, the collection one
+    /// otherwise.
+    pub fn get_token_expiry_policy(&self, token_id: TokenId) -> Option<ExpiryPolicy> {
+        self.token_expiry_policy(&token_id)
+    }
+
+    /// Sets how long (same format as `expiration_period` in `nft_mint`) tokens of the
+    /// collection can still be renewed after they expire. During that time they stay
+    /// visible but can't be transferred, approved or sold, and their expiry policy isn't
+    /// applied yet. `None` removes the grace period.
+    pub fn set_grace_period(&mut self, grace_period: Option<String>) {
+        self.assert_role(Role::Admin);
+        self.grace_period = grace_period.map_or(0, |grace_period| parse_time(&grace_period));
+    }
+
+    /// Grace period of the collection in nanoseconds.
+    pub fn get_grace_period(&self) -> u64 {
+        self.grace_period
+    }
+
+    /// Overrides the grace period of the collection for `token_id`; `None` removes the
+    /// override.
+    #[payable]
+    pub fn set_token_grace_period(&mut self, token_id: TokenId, grace_period: Option<String>) {
+        self.assert_role(Role::Admin);
+        require!(
+            self.tokens.owner_by_id.contains_key(&token_id),
+            "Token not found"
+        );
+        let initial_storage_usage = env::storage_usage();
+        match grace_period {
+            Some(grace_period) => self
+                .token_grace_periods
+                .insert(&token_id, &parse_time(&grace_period)),
+            None => self.token_grace_periods.remove(&token_id),
+        };
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Grace period of `token_id` in nanoseconds: its own one if set, the collection one
+    /// otherwise.
+    pub fn get_token_grace_period(&self, token_id: TokenId) -> u64 {
+        self.token_grace_period(&token_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn token_grace_period(&self, token_id: &TokenId) -> u64 {
+        self.token_grace_periods
+            .get(token_id)
+            .unwrap_or(self.grace_period)
+    }
+
+    pub(crate) fn token_expiry_policy(&self, token_id: &TokenId) -> Option<ExpiryPolicy> {
+        self.token_expiry_policies
+            .get(token_id)
+            .or(self.expiry_policy)
+    }
+
+    /// Owner of `token_id` as shown by views, `None` if views hide the token: an expired
+    /// token is hidden unless its policy is `Freeze`, or `RevertToIssuer` (it is shown with
+    /// its owner until it is actually reverted, so that all views agree).
+    pub(crate) fn token_view_owner(
+        &self,
+        token_id: &TokenId,
+        owner_id: AccountId,
+    ) -> Option<AccountId> {
+        if !self.token_is_expired(token_id) {
+            return Some(owner_id);
+        }
+        match self.token_expiry_policy(token_id) {
+            Some(ExpiryPolicy::Freeze) | Some(ExpiryPolicy::RevertToIssuer) => Some(owner_id),
+            Some(ExpiryPolicy::Burn) | None => None,
+        }
+    }
+
+    /// Applies the expiry policy of `token_id` if it has expired. Returns `true` if the
+    /// token was burned or reverted to its issuer, in which case the caller must return
+    /// without panicking so that the change is kept.
+    pub(crate) fn internal_apply_expiry_policy(&mut self, token_id: &TokenId) -> bool {
+        if !self.token_is_expired(token_id) {
+            return false;
+        }
+        match self.token_expiry_policy(token_id) {
+            Some(ExpiryPolicy::Burn) => {
+                self.internal_burn_expired(vec![token_id.clone()]);
+                true
+            }
+            Some(ExpiryPolicy::RevertToIssuer) => {
+                self.internal_revert_to_issuer(token_id);
+                true
+            }
+            Some(ExpiryPolicy::Freeze) | None => false,
+        }
+    }
+
+    fn token_issuer(&self, token_id: &TokenId, owner_id: &AccountId) -> AccountId {
+        self.token_creator(token_id)
+            .filter(|issuer_id| issuer_id != owner_id)
+            .unwrap_or_else(env::current_account_id)
+    }
+
+    pub(crate) fn internal_revert_to_issuer(&mut self, token_id: &TokenId) {
+        let owner_id = self.tokens.owner_by_id.get(token_id).unwrap();
+        let issuer_id = self.token_issuer(token_id, &owner_id);
+        if let Some(approved_account_ids) = self
+            .tokens
+            .approvals_by_id
+            .as_mut()
+            .and_then(|by_id| by_id.remove(token_id))
+        {
+            refund_approved_account_ids(owner_id.clone(), &approved_account_ids);
+        }
+        self.internal_unmark_expired(token_id, &owner_id);
+        self.tokens
+            .internal_transfer_unguarded(token_id, &owner_id, &issuer_id);
+        self.internal_clear_expiration(token_id);
+
+        NftTransfer {
+            old_owner_id: &owner_id,
+            new_owner_id: &issuer_id,
+            token_ids: &[token_id],
+            authorized_id: None,
+            memo: Some("expired"),
+        }
+        .emit();
+    }
+}
@@ -1,8 +1,11 @@
 use near_contract_standards::non_fungible_token::{
     core::NonFungibleTokenResolver, metadata::NonFungibleTokenMetadataProvider,
 };
+use near_sdk::json_types::Base64VecU8;
 use near_sdk::near_bindgen;
 
+use crate::my_events::ContractMetadataUpdate;
+use crate::my_internal::refund_storage_delta;
 use crate::*;
 
 #[near_bindgen]
@@ -15,12 +18,17 @@ impl NonFungibleTokenResolver for Contract {
         token_id: TokenId,
         approved_account_ids: Option<std::collections::HashMap<AccountId, u64>>,
     ) -> bool {
-        self.tokens.nft_resolve_transfer(
-            previous_owner_id,
-            receiver_id,
-            token_id,
+        let transferred = self.tokens.nft_resolve_transfer(
+            previous_owner_id.clone(),
+            receiver_id.clone(),
+            token_id.clone(),
             approved_account_ids,
-        )
+        );
+        // the token may have expired, and been purged, while the transfer was in flight
+        if !transferred {
+            self.internal_move_expired(&token_id, &receiver_id, &previous_owner_id);
+        }
+        transferred
     }
 }
 
@@ -30,3 +38,63 @@ impl NonFungibleTokenMetadataProvider for Contract {
         self.metadata.get().unwrap()
     }
 }
+
+#[near_bindgen]
+impl Contract {
+    /// Replaces the metadata of the collection. The attached deposit must cover any extra
+    /// storage; storage freed by the change is refunded.
+    #[payable]
+    pub fn set_contract_metadata(&mut self, metadata: NFTContractMetadata) {
+        self.internal_set_contract_metadata(metadata, &["metadata"]);
+    }
+
+    #[payable]
+    pub fn set_contract_name(&mut self, name: String) {
+        let mut metadata = self.metadata.get().unwrap();
+        metadata.name = name;
+        self.internal_set_contract_metadata(metadata, &["name"]);
+    }
+
+    #[payable]
+    pub fn set_contract_icon(&mut self, icon: Option<String>) {
+        let mut metadata = self.metadata.get().unwrap();
+        metadata.icon = icon;
+        self.internal_set_contract_metadata(metadata, &["icon"]);
+    }
+
+    #[payable]
+    pub fn set_contract_base_uri(&mut self, base_uri: Option<String>) {
+        let mut metadata = self.metadata.get().unwrap();
+        metadata.base_uri = base_uri;
+        self.internal_set_contract_metadata(metadata, &["base_uri"]);
+    }
+
+    /// `reference` and `reference_hash` are set together, as either both or none of them
+    /// must be present.
+    #[payable]
+    pub fn set_contract_reference(
+        &mut self,
+        reference: Option<String>,
+        reference_hash: Option<Base64VecU8>,
+    ) {
+        let mut metadata = self.metadata.get().unwrap();
+        metadata.reference = reference;
+        metadata.reference_hash = reference_hash;
+        self.internal_set_contract_metadata(metadata, &["reference", "reference_hash"]);
+    }
+}
+
+impl Contract {
+    fn internal_set_contract_metadata(&mut self, metadata: NFTContractMetadata, fields: &[&str]) {
+        self.assert_owner();
+        metadata.assert_valid();
+        let initial_storage_usage = env::storage_usage();
+        self.metadata.set(&metadata);
+        refund_storage_delta(initial_storage_usage);
+        ContractMetadataUpdate {
+            fields,
+            authorized_id: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+}
@@ -0,0 +1,169 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json::json;
+use near_sdk::{is_promise_success, near_bindgen, require, Balance, Gas, ONE_NEAR};
+
+use crate::*;
+
+const GAS_FOR_COLLECTION_INIT: Gas = Gas(50_000_000_000_000);
+const GAS_FOR_ON_COLLECTION_CREATED: Gas = Gas(20_000_000_000_000);
+// balance left on a new collection account (on top of its code) to pay for its own state
+const MIN_COLLECTION_STATE_DEPOSIT: Balance = ONE_NEAR / 10;
+
+/// Code deployed to every new collection: this contract built without the `factory`
+/// feature, see `scripts/build.sh`.
+#[cfg(all(feature = "factory", not(test)))]
+const COLLECTION_CODE: Option<&[u8]> = Some(include_bytes!("../res/collection.wasm"));
+#[cfg(all(not(feature = "factory"), not(test)))]
+const COLLECTION_CODE: Option<&[u8]> = None;
+#[cfg(test)]
+const COLLECTION_CODE: Option<&[u8]> = Some(&[0u8; 100]);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionInfo {
+    pub owner_id: AccountId,
+    pub creator_id: AccountId,
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct JsonCollection {
+    pub collection_id: AccountId,
+    pub owner_id: AccountId,
+    pub creator_id: AccountId,
+    pub created_at: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Creates `<prefix>.<factory>`, deploys the embedded collection code there and
+    /// initializes it with `new(owner_id, metadata)`. The attached deposit must cover the
+    /// registry entry, the code storage of the new account and
+    /// `MIN_COLLECTION_STATE_DEPOSIT`; everything except the registry entry is sent to the
+    /// new account.
+    #[payable]
+    pub fn create_collection(
+        &mut self,
+        prefix: String,
+        owner_id: AccountId,
+        metadata: NFTContractMetadata,
+    ) -> Promise {
+        metadata.assert_valid();
+        require!(
+            COLLECTION_CODE.is_some(),
+            "This build doesn't embed the collection code"
+        );
+        let code = COLLECTION_CODE.unwrap();
+
+        require!(
+            !prefix.is_empty() && !prefix.contains('.'),
+            "Invalid collection prefix"
+        );
+        let collection_id =
+            format!("{}.{}", prefix, env::current_account_id()).parse::<AccountId>();
+        require!(collection_id.is_ok(), "Invalid collection prefix");
+        let collection_id = collection_id.unwrap();
+        require!(
+            self.collections.get(&collection_id).is_none(),
+            "Collection already exists"
+        );
+
+        let creator_id = env::predecessor_account_id();
+        let deposit = env::attached_deposit();
+
+        let initial_storage_usage = env::storage_usage();
+        self.collections.insert(
+            &collection_id,
+            &CollectionInfo {
+                owner_id: owner_id.clone(),
+                creator_id: creator_id.clone(),
+                created_at: env::block_timestamp(),
+            },
+        );
+        let registry_cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        let code_cost = code.len() as Balance * env::storage_byte_cost();
+        let required_deposit = registry_cost + code_cost + MIN_COLLECTION_STATE_DEPOSIT;
+        require!(
+            deposit >= required_deposit,
+            format!(
+                "Must attach at least {} yoctoNEAR to create a collection",
+                required_deposit
+            )
+        );
+
+        Promise::new(collection_id.clone())
+            .create_account()
+            .transfer(deposit - registry_cost)
+            .deploy_contract(code.to_vec())
+            .function_call(
+                "new".to_string(),
+                json!({ "owner_id": owner_id, "metadata": metadata })
+                    .to_string()
+                    .into_bytes(),
+                0,
+                GAS_FOR_COLLECTION_INIT,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_COLLECTION_CREATED)
+                    .on_collection_created(collection_id, creator_id, U128(deposit)),
+            )
+    }
+
+    /// Rolls the registry back and refunds the creator if the deploy batch failed.
+    /// A failed batch returns the transferred balance to the factory, so the whole
+    /// deposit can be refunded.
+    #[private]
+    pub fn on_collection_created(
+        &mut self,
+        collection_id: AccountId,
+        creator_id: AccountId,
+        deposit: U128,
+    ) -> bool {
+        if is_promise_success() {
+            return true;
+        }
+        env::log_str(&format!("Failed to create collection {}", collection_id));
+        self.collections.remove(&collection_id);
+        Promise::new(creator_id).transfer(deposit.into());
+        false
+    }
+
+    pub fn get_collection(&self, collection_id: AccountId) -> Option<JsonCollection> {
+        self.collections
+            .get(&collection_id)
+            .map(|info| Self::collection_to_json(collection_id, info))
+    }
+
+    pub fn get_collections(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<JsonCollection> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        require!(
+            (self.collections.len() as u128) >= start_index,
+            "Out of bounds, please use a smaller from_index."
+        );
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        require!(limit != 0, "Cannot provide limit of 0.");
+        self.collections
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|(collection_id, info)| Self::collection_to_json(collection_id, info))
+            .collect()
+    }
+
+    fn collection_to_json(collection_id: AccountId, info: CollectionInfo) -> JsonCollection {
+        JsonCollection {
+            collection_id,
+            owner_id: info.owner_id,
+            creator_id: info.creator_id,
+            created_at: info.created_at,
+        }
+    }
+}
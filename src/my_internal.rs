@@ -1,3 +1,5 @@
+use chrono::DateTime;
+use near_contract_standards::non_fungible_token::refund_deposit;
 use near_sdk::{json_types::U128, require, Balance};
 
 use crate::*;
@@ -6,37 +8,423 @@ pub(crate) fn royalty_to_payout(royalty_percentage: u32, amount_to_pay: Balance)
     U128(royalty_percentage as u128 * amount_to_pay / 10_000u128)
 }
 
-pub(crate) fn parse_time(time: &String) -> u64 {
-    let n = time.len();
-    let num = &time[..(n - 1)].parse::<u64>();
-    let period = time.as_bytes()[n - 1];
+const SECOND: u64 = 1_000_000_000;
+const MINUTE: u64 = 60 * SECOND;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+// calendar units have a fixed length, since durations aren't tied to a date
+const MONTH: u64 = 30 * DAY;
+const YEAR: u64 = 365 * DAY;
+
+/// Parses a duration into nanoseconds, panicking with a descriptive error if it's malformed.
+/// See `parse_duration` for the accepted formats.
+pub(crate) fn parse_time(time: &str) -> u64 {
+    match parse_duration(time) {
+        Ok(duration) => duration,
+        Err(err) => panic!("Invalid duration {:?}: {}", time, err),
+    }
+}
+
+/// Parses a duration into nanoseconds. Two formats are accepted:
+/// * one or more `<number><unit>` parts, e.g. `30d` or `1d12h30m`, where the unit is one of
+///   `s`, `m`, `h`, `d`, `w`, `mo` (30 days) or `y` (365 days);
+/// * ISO 8601 durations, e.g. `P1DT12H` or `PT30M`, with the same lengths of months and years.
+pub(crate) fn parse_duration(time: &str) -> Result<u64, String> {
+    if time.is_empty() {
+        return Err("duration is empty".to_string());
+    }
+    match time.strip_prefix('P') {
+        Some(iso) => parse_iso_duration(iso),
+        None => {
+            let mut total: u64 = 0;
+            for (amount, unit) in duration_parts(time)? {
+                let unit = match unit {
+                    "s" => SECOND,
+                    "m" => MINUTE,
+                    "h" => HOUR,
+                    "d" => DAY,
+                    "w" => WEEK,
+                    "mo" => MONTH,
+                    "y" => YEAR,
+                    _ => return Err(format!("unknown unit {:?}", unit)),
+                };
+                total = add_part(total, amount, unit)?;
+            }
+            Ok(total)
+        }
+    }
+}
+
+/// Parses the part of an ISO 8601 duration after the leading `P`.
+fn parse_iso_duration(iso: &str) -> Result<u64, String> {
+    let (date, time) = match iso.split_once('T') {
+        Some((_, "")) => return Err("no time components after T".to_string()),
+        Some((date, time)) => (date, Some(time)),
+        None => (iso, None),
+    };
+    if date.is_empty() && time.is_none() {
+        return Err("no duration components after P".to_string());
+    }
+
+    let mut total: u64 = 0;
+    let sections = [
+        (
+            date,
+            &[("Y", YEAR), ("M", MONTH), ("W", WEEK), ("D", DAY)][..],
+        ),
+        (
+            time.unwrap_or_default(),
+            &[("H", HOUR), ("M", MINUTE), ("S", SECOND)][..],
+        ),
+    ];
+    for (section, units) in sections {
+        // components must follow the order of `units`, each at most once
+        let mut next_unit = 0;
+        for (amount, designator) in duration_parts(section)? {
+            let position = units[next_unit..]
+                .iter()
+                .position(|(name, _)| *name == designator);
+            let position = match position {
+                Some(position) => next_unit + position,
+                None => return Err(format!("unexpected designator {:?}", designator)),
+            };
+            total = add_part(total, amount, units[position].1)?;
+            next_unit = position + 1;
+        }
+    }
+    Ok(total)
+}
+
+/// Splits `1d12h` into `[(1, "d"), (12, "h")]`.
+fn duration_parts(time: &str) -> Result<Vec<(u64, &str)>, String> {
+    let mut parts = Vec::new();
+    let mut rest = time;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(format!("expected a number at {:?}", rest));
+        }
+        let amount = rest[..digits]
+            .parse::<u64>()
+            .map_err(|_| format!("number {} is too large", &rest[..digits]))?;
+        rest = &rest[digits..];
+        let letters = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        if letters == 0 {
+            return Err(format!("missing unit after {}", amount));
+        }
+        parts.push((amount, &rest[..letters]));
+        rest = &rest[letters..];
+    }
+    Ok(parts)
+}
+
+fn add_part(total: u64, amount: u64, unit: u64) -> Result<u64, String> {
+    amount
+        .checked_mul(unit)
+        .and_then(|part| total.checked_add(part))
+        .ok_or_else(|| "duration is too long".to_string())
+}
+
+/// Parses an absolute date, given either as Unix epoch milliseconds or as an RFC 3339 date
+/// such as `2024-01-31T12:00:00Z`, into nanoseconds.
+pub(crate) fn parse_timestamp(time: &str) -> u64 {
+    let timestamp = if !time.is_empty() && time.bytes().all(|c| c.is_ascii_digit()) {
+        time.parse::<u64>()
+            .ok()
+            .and_then(|ms| ms.checked_mul(1_000_000))
+    } else {
+        DateTime::parse_from_rfc3339(time).ok().and_then(|date| {
+            u64::try_from(date.timestamp())
+                .ok()?
+                .checked_mul(SECOND)?
+                .checked_add(u64::from(date.timestamp_subsec_nanos()))
+        })
+    };
+    match timestamp {
+        Some(timestamp) => timestamp,
+        None => panic!(
+            "Invalid date {:?}: expected Unix milliseconds or an RFC 3339 date",
+            time
+        ),
+    }
+}
+
+/// Formats a timestamp in nanoseconds the way NEP-177 expects dates in token metadata
+/// (Unix epoch milliseconds).
+pub(crate) fn metadata_date(timestamp: u64) -> String {
+    (timestamp / 1_000_000).to_string()
+}
+
+/// Expiration date of a new token, given either its lifetime (`expiration_period`) or the
+/// absolute date it expires at (`expires_at`).
+pub(crate) fn expiration_date(
+    expiration_period: Option<&str>,
+    expires_at: Option<&str>,
+) -> Option<u64> {
     require!(
-        period == b's' || period == b'm' || period == b'h' || period == b'd',
-        "Wrong time format."
+        expiration_period.is_none() || expires_at.is_none(),
+        "Only one of expiration_period and expires_at can be given"
     );
-    require!(num.is_ok(), "Wrong time format.");
-    let time: u64 = match period {
-        b's' => 1_000_000_000,
-        b'm' => 60 * 1_000_000_000,
-        b'h' => 60 * 60 * 1_000_000_000,
-        _ => 24 * 60 * 60 * 1_000_000_000,
-    };
-    num.as_ref().unwrap() * time
+    match (expiration_period, expires_at) {
+        (Some(period), _) => {
+            let expiration_date = env::block_timestamp().checked_add(parse_time(period));
+            require!(
+                expiration_date.is_some(),
+                "Expiration date is too far in the future"
+            );
+            expiration_date
+        }
+        (None, Some(date)) => {
+            let expiration_date = parse_timestamp(date);
+            require!(
+                expiration_date > env::block_timestamp(),
+                "Expiration date must be in the future"
+            );
+            Some(expiration_date)
+        }
+        (None, None) => None,
+    }
+}
+
+/// Settles the storage of a change made since `initial_storage_usage`: the attached deposit
+/// must cover the storage added, and the storage freed is refunded to the caller along with
+/// the rest of the deposit.
+pub(crate) fn refund_storage_delta(initial_storage_usage: u64) {
+    let final_storage_usage = env::storage_usage();
+    if final_storage_usage >= initial_storage_usage {
+        refund_deposit(final_storage_usage - initial_storage_usage);
+    } else {
+        let freed =
+            Balance::from(initial_storage_usage - final_storage_usage) * env::storage_byte_cost();
+        Promise::new(env::predecessor_account_id()).transfer(freed + env::attached_deposit());
+    }
+}
+
+/// sha256 of the borsh-serialized token metadata.
+pub(crate) fn metadata_hash(metadata: &TokenMetadata) -> Vec<u8> {
+    env::sha256(&metadata.try_to_vec().unwrap_or_else(|_| env::abort()))
 }
 
 impl Contract {
-    pub(crate) fn token_is_not_expired(&self, token_id: &TokenId) -> bool {
-        let timestamp = self.expiration_timestamp.get(&token_id);
-        timestamp.is_none() || timestamp.unwrap() >= env::block_timestamp()
+    /// Royalty of the token: its own royalty map if it was given one at mint, the collection
+    /// default royalty otherwise. Tokens minted before default royalties existed always
+    /// stored a map (possibly empty), so they keep their royalties.
+    pub(crate) fn token_royalty(&self, token_id: &TokenId) -> HashMap<AccountId, u32> {
+        self.royalty
+            .get(token_id)
+            .or_else(|| self.default_royalty.get())
+            .unwrap_or_default()
     }
 
-    pub(crate) fn enum_get_token(&self, owner_id: AccountId, token_id: TokenId) -> JsonToken {
-        let metadata = self
+    pub(crate) fn assert_valid_royalties(&self, royalties: &HashMap<AccountId, u32>) {
+        //make sure that the length of the perpetual royalties is below 7 since we won't have enough GAS to pay out that many people
+        assert!(
+            royalties.len() < 7,
+            "Cannot add more than 6 perpetual royalty amounts"
+        );
+        let mut total: u64 = 0;
+        for (account_id, amount) in royalties {
+            require!(
+                *amount > 0,
+                format!("Royalty of {} must be greater than zero", account_id)
+            );
+            total += u64::from(*amount);
+        }
+        require!(
+            total <= u64::from(self.max_royalty),
+            format!(
+                "Total royalty of {} basis points exceeds the maximum of {}",
+                total, self.max_royalty
+            )
+        );
+    }
+
+    /// Where the current time is relative to the start and expiration dates of `token_id`.
+    pub(crate) fn token_status(&self, token_id: &TokenId) -> TokenStatus {
+        let now = env::block_timestamp();
+        if self
+            .expiration_timestamp
+            .get(token_id)
+            .is_some_and(|expiration_date| expiration_date < now)
+        {
+            TokenStatus::Expired
+        } else if self
+            .start_timestamp
+            .get(token_id)
+            .is_some_and(|start_date| start_date > now)
+        {
+            TokenStatus::Pending
+        } else {
+            TokenStatus::Active
+        }
+    }
+
+    /// The token has expired and its grace period is over.
+    pub(crate) fn token_is_expired(&self, token_id: &TokenId) -> bool {
+        self.token_status(token_id) == TokenStatus::Expired && !self.token_in_grace(token_id)
+    }
+
+    /// The token has expired but is still within its grace period.
+    pub(crate) fn token_in_grace(&self, token_id: &TokenId) -> bool {
+        let now = env::block_timestamp();
+        self.expiration_timestamp
+            .get(token_id)
+            .is_some_and(|expiration_date| {
+                expiration_date < now
+                    && now <= expiration_date.saturating_add(self.token_grace_period(token_id))
+            })
+    }
+
+    /// Account that issued `token_id`: the contract account for tokens bought in the sale,
+    /// the account that minted it otherwise.
+    pub(crate) fn token_creator(&self, token_id: &TokenId) -> Option<AccountId> {
+        self.issued_by
+            .get(token_id)
+            .or_else(|| self.minted_by.get(token_id))
+    }
+
+    /// Moves the expiration date of `token_id`, keeping `expires_at` of its metadata in sync.
+    pub(crate) fn internal_set_expiration(&mut self, token_id: &TokenId, expiration_date: u64) {
+        self.expiration_timestamp.insert(token_id, &expiration_date);
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            if let Some(mut metadata) = token_metadata_by_id.get(token_id) {
+                metadata.expires_at = Some(metadata_date(expiration_date));
+                token_metadata_by_id.insert(token_id, &metadata);
+            }
+        }
+        if expiration_date > env::block_timestamp() {
+            if let Some(owner_id) = self.tokens.owner_by_id.get(token_id) {
+                self.internal_unmark_expired(token_id, &owner_id);
+            }
+        }
+    }
+
+    /// Removes the expiration date of `token_id` and `expires_at` from its metadata.
+    pub(crate) fn internal_clear_expiration(&mut self, token_id: &TokenId) {
+        self.expiration_timestamp.remove(token_id);
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            if let Some(mut metadata) = token_metadata_by_id.get(token_id) {
+                metadata.expires_at = None;
+                token_metadata_by_id.insert(token_id, &metadata);
+            }
+        }
+    }
+
+    /// Takes the expired `token_id`, owned by `owner_id`, out of `nft_active_supply`. It
+    /// stays out until it is burned, renewed or reverted to its issuer.
+    pub(crate) fn internal_mark_expired(&mut self, token_id: &TokenId, owner_id: &AccountId) {
+        if self.expired_tokens.insert(token_id) {
+            self.expired_supply += 1;
+            self.add_expired_supply_for(owner_id);
+        }
+    }
+
+    /// Undoes `internal_mark_expired`, if `token_id` was marked.
+    pub(crate) fn internal_unmark_expired(&mut self, token_id: &TokenId, owner_id: &AccountId) {
+        if self.expired_tokens.remove(token_id) {
+            self.expired_supply = self.expired_supply.saturating_sub(1);
+            self.remove_expired_supply_for(owner_id);
+        }
+    }
+
+    /// Moves `token_id` from the expired supply of `old_owner_id` to the one of
+    /// `new_owner_id` if it is marked expired. Every transfer must call this.
+    pub(crate) fn internal_move_expired(
+        &mut self,
+        token_id: &TokenId,
+        old_owner_id: &AccountId,
+        new_owner_id: &AccountId,
+    ) {
+        if self.expired_tokens.contains(token_id) {
+            self.remove_expired_supply_for(old_owner_id);
+            self.add_expired_supply_for(new_owner_id);
+        }
+    }
+
+    fn add_expired_supply_for(&mut self, owner_id: &AccountId) {
+        let expired = self
+            .expired_supply_per_owner
+            .get(owner_id)
+            .unwrap_or_default();
+        self.expired_supply_per_owner
+            .insert(owner_id, &(expired + 1));
+    }
+
+    fn remove_expired_supply_for(&mut self, owner_id: &AccountId) {
+        match self.expired_supply_per_owner.get(owner_id) {
+            Some(expired) if expired > 1 => {
+                self.expired_supply_per_owner
+                    .insert(owner_id, &(expired - 1));
+            }
+            _ => {
+                self.expired_supply_per_owner.remove(owner_id);
+            }
+        }
+    }
+
+    pub(crate) fn assert_token_active(&self, token_id: &TokenId) {
+        let status = self.token_status(token_id);
+        require!(status != TokenStatus::Pending, "Token is not active yet");
+        require!(
+            !self.token_in_grace(token_id),
+            "Token is in its grace period and can only be renewed"
+        );
+        require!(status != TokenStatus::Expired, "Token is expired");
+    }
+
+    /// Removes all per-token state of `token_id`. Returns the owner of the burned token and
+    /// the approvals it had, so that the caller can refund their storage.
+    pub(crate) fn internal_burn(
+        &mut self,
+        token_id: &TokenId,
+    ) -> (AccountId, Option<HashMap<AccountId, u64>>) {
+        let owner_id = self.tokens.owner_by_id.remove(token_id);
+        require!(owner_id.is_some(), "Token not found");
+        let owner_id = owner_id.unwrap();
+
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            if let Some(mut token_ids) = tokens_per_owner.get(&owner_id) {
+                token_ids.remove(token_id);
+                if token_ids.is_empty() {
+                    tokens_per_owner.remove(&owner_id);
+                } else {
+                    tokens_per_owner.insert(&owner_id, &token_ids);
+                }
+            }
+        }
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.remove(token_id);
+        }
+        let approved_account_ids = self
             .tokens
-            .token_metadata_by_id
-            .as_ref()
-            .unwrap()
-            .get(&token_id);
+            .approvals_by_id
+            .as_mut()
+            .and_then(|by_id| by_id.remove(token_id));
+        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
+            next_approval_id_by_id.remove(token_id);
+        }
+        self.royalty.remove(token_id);
+        self.expiration_timestamp.remove(token_id);
+        self.start_timestamp.remove(token_id);
+        self.minted_by.remove(token_id);
+        self.issued_by.remove(token_id);
+        self.metadata_history.remove(token_id);
+        self.token_expiry_policies.remove(token_id);
+        self.token_grace_periods.remove(token_id);
+        self.private_metadata.remove(token_id);
+        self.internal_unmark_expired(token_id, &owner_id);
+
+        (owner_id, approved_account_ids)
+    }
+
+    /// Builds the view of a token, with only the public part of its metadata, see
+    /// `nft_token`.
+    pub(crate) fn enum_get_token(&self, owner_id: AccountId, token_id: TokenId) -> JsonToken {
         let approved_account_ids = Some(
             self.tokens
                 .approvals_by_id
@@ -45,14 +433,21 @@ impl Contract {
                 .get(&token_id)
                 .unwrap_or_default(),
         );
-        let expiration_date = self.expiration_timestamp.get(&token_id).map(|x| x as u64);
-        let royalty = self.royalty.get(&token_id).unwrap();
+        let expiration_date = self.expiration_timestamp.get(&token_id);
+        let start_date = self.start_timestamp.get(&token_id);
+        let status = self.token_status(&token_id);
+        let in_grace = self.token_in_grace(&token_id);
+        let royalty = self.token_royalty(&token_id);
+        let metadata = self.token_public_metadata(&token_id);
         JsonToken {
             token_id,
             owner_id,
             metadata,
             approved_account_ids,
             expiration_date,
+            start_date,
+            status,
+            in_grace,
             royalty,
         }
     }
@@ -0,0 +1,258 @@
+use ed25519_dalek::Verifier;
+use near_contract_standards::non_fungible_token::refund_deposit;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::Deserialize;
+use near_sdk::{near_bindgen, require, CurveType, PublicKey};
+
+use crate::my_events::NftMetadataUpdate;
+use crate::my_internal::{metadata_date, metadata_hash, parse_timestamp, refund_storage_delta};
+use crate::my_roles::Role;
+use crate::*;
+
+/// How long a signed `nft_token_metadata` request stays valid, in milliseconds, on either
+/// side of the current block time.
+pub const METADATA_SIGNATURE_TTL: u64 = 5 * 60 * 1_000;
+
+/// Metadata of a token as stored by the contract: encrypted off-chain, so the contract
+/// (and anyone reading its state) only ever sees the ciphertext. The encryption is the only
+/// thing keeping the metadata confidential, and it is never redone by the contract: after a
+/// transfer the ciphertext is still the one made for the previous holders until the creator
+/// replaces it with `nft_update_metadata`, and tokens bought in the sale all share the
+/// ciphertext of the sale config.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PrivateMetadata {
+    /// The NEP-177 metadata of the token, encrypted for its holders.
+    pub ciphertext: Base64VecU8,
+    /// sha256 of the plaintext metadata, so that a copy revealed by the owner can be
+    /// checked by anyone.
+    pub hash: Base64VecU8,
+}
+
+/// Changes to the metadata of a token; the fields left out keep their value. The encrypted
+/// metadata can only be replaced as a whole, since the contract can't read it. Of the
+/// public dates only `issued_at` can be changed: `expires_at` and `starts_at` follow the
+/// validity window of the token (`nft_renew` moves the expiration date), and `updated_at`
+/// is set by the update itself.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenMetadataPatch {
+    pub metadata: Option<PrivateMetadata>,
+    /// Unix epoch in milliseconds or RFC 3339, stored in milliseconds.
+    pub issued_at: Option<String>,
+}
+
+impl PrivateMetadata {
+    pub fn assert_valid(&self) {
+        require!(
+            !self.ciphertext.0.is_empty(),
+            "Metadata ciphertext is empty"
+        );
+        require!(self.hash.0.len() == 32, "Metadata hash must be 32 bytes");
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers the ed25519 key the caller signs `nft_token_metadata` requests with,
+    /// replacing the previous one. The attached deposit must cover its storage.
+    #[payable]
+    pub fn set_metadata_key(&mut self, public_key: PublicKey) {
+        require!(
+            public_key.curve_type() == CurveType::ED25519,
+            "Metadata key must be an ed25519 key"
+        );
+        let initial_storage_usage = env::storage_usage();
+        self.metadata_keys
+            .insert(&env::predecessor_account_id(), &public_key);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn get_metadata_key(&self, account_id: AccountId) -> Option<PublicKey> {
+        self.metadata_keys.get(&account_id)
+    }
+
+    /// Returns the encrypted metadata of `token_id` to its owner or to the contract owner.
+    /// `account_id` is one of them, and `signature` is its signature, made with the key
+    /// registered with `set_metadata_key`, of
+    /// `nft_token_metadata:<contract id>:<token_id>:<account_id>:<signed_at>`, where
+    /// `signed_at` is the time of signing in Unix milliseconds, within
+    /// `METADATA_SIGNATURE_TTL` of the block time.
+    ///
+    /// This is a convenience, not an access control: the ciphertext is in the contract
+    /// state, which anyone can read with the `view_state` RPC.
+    pub fn nft_token_metadata(
+        &self,
+        token_id: TokenId,
+        account_id: AccountId,
+        signed_at: u64,
+        signature: Base64VecU8,
+    ) -> Option<PrivateMetadata> {
+        let owner_id = self.tokens.owner_by_id.get(&token_id)?;
+        require!(
+            account_id == owner_id
+                || account_id == self.tokens.owner_id
+                || account_id == env::current_account_id(),
+            "Only the token owner can read its metadata"
+        );
+        let now = env::block_timestamp() / 1_000_000;
+        require!(
+            signed_at.abs_diff(now) <= METADATA_SIGNATURE_TTL,
+            "Metadata request signature has expired"
+        );
+        let message = format!(
+            "nft_token_metadata:{}:{}:{}:{}",
+            env::current_account_id(),
+            token_id,
+            account_id,
+            signed_at
+        );
+        require!(
+            self.metadata_keys
+                .get(&account_id)
+                .is_some_and(|public_key| verify_signature(
+                    &public_key,
+                    message.as_bytes(),
+                    &signature.0
+                )),
+            "Invalid metadata request signature"
+        );
+        self.private_metadata.get(&token_id)
+    }
+
+    /// sha256 of the plaintext metadata of `token_id`, as committed to at mint or by the
+    /// last `nft_update_metadata`.
+    pub fn nft_token_metadata_hash(&self, token_id: TokenId) -> Option<Base64VecU8> {
+        self.token_metadata_commitment(&token_id)
+            .map(Base64VecU8::from)
+    }
+
+    /// Merges `patch` into the metadata of `token_id` and sets `updated_at` of its public
+    /// metadata (Unix epoch in milliseconds). Can be called by the creator of the token (the
+    /// account that minted it, or the contract account for tokens bought in the sale) or by
+    /// an admin. The hash of the previous encrypted metadata is added to the history of the
+    /// token, and the hash of the current one is returned. The attached deposit must cover
+    /// the storage the update adds, and storage freed by the update is refunded.
+    #[payable]
+    pub fn nft_update_metadata(
+        &mut self,
+        token_id: TokenId,
+        patch: TokenMetadataPatch,
+    ) -> Base64VecU8 {
+        self.assert_not_paused();
+        require!(
+            self.tokens.owner_by_id.contains_key(&token_id),
+            "Token not found"
+        );
+        let predecessor_id = env::predecessor_account_id();
+        require!(
+            self.token_creator(&token_id).as_ref() == Some(&predecessor_id)
+                || self.internal_has_role(&predecessor_id, Role::Admin),
+            "Only the token creator or an admin can update its metadata"
+        );
+        require!(
+            patch.metadata.is_some() || patch.issued_at.is_some(),
+            "Nothing to update"
+        );
+        match &patch.metadata {
+            Some(metadata) => metadata.assert_valid(),
+            // the plaintext of a migrated token would be lost with its other public fields
+            None => require!(
+                self.private_metadata.contains_key(&token_id),
+                "The metadata of this token must be encrypted first"
+            ),
+        }
+        let previous_hash = self.token_metadata_commitment(&token_id).unwrap();
+
+        let initial_storage_usage = env::storage_usage();
+        if let Some(metadata) = &patch.metadata {
+            self.private_metadata.insert(&token_id, metadata);
+        }
+        let token_metadata_by_id = self.tokens.token_metadata_by_id.as_mut().unwrap();
+        let mut public_metadata = public_metadata(token_metadata_by_id.get(&token_id).unwrap());
+        if let Some(issued_at) = patch.issued_at {
+            public_metadata.issued_at = Some(metadata_date(parse_timestamp(&issued_at)));
+        }
+        public_metadata.updated_at = Some(metadata_date(env::block_timestamp()));
+        token_metadata_by_id.insert(&token_id, &public_metadata);
+        let mut history = self.metadata_history.get(&token_id).unwrap_or_default();
+        history.push(previous_hash.clone());
+        self.metadata_history.insert(&token_id, &history);
+        refund_storage_delta(initial_storage_usage);
+
+        let new_hash = Base64VecU8::from(self.token_metadata_commitment(&token_id).unwrap());
+        NftMetadataUpdate {
+            token_id: &token_id,
+            previous_hash: &Base64VecU8::from(previous_hash),
+            new_hash: &new_hash,
+            authorized_id: &predecessor_id,
+        }
+        .emit();
+        new_hash
+    }
+
+    /// Hashes of the previous versions of the metadata of `token_id`, oldest first.
+    pub fn nft_metadata_history(&self, token_id: TokenId) -> Vec<Base64VecU8> {
+        self.metadata_history
+            .get(&token_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(Base64VecU8::from)
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Hash of the metadata of `token_id`. Tokens minted before metadata was encrypted
+    /// still have their plaintext in `TokenMetadata` until it is replaced with
+    /// `nft_update_metadata`; their commitment is the hash of that plaintext.
+    pub(crate) fn token_metadata_commitment(&self, token_id: &TokenId) -> Option<Vec<u8>> {
+        match self.private_metadata.get(token_id) {
+            Some(metadata) => Some(metadata.hash.0),
+            None => self
+                .tokens
+                .token_metadata_by_id
+                .as_ref()
+                .and_then(|by_id| by_id.get(token_id))
+                .map(|metadata| metadata_hash(&metadata)),
+        }
+    }
+
+    /// The public part of the metadata of `token_id`, as returned by views.
+    pub(crate) fn token_public_metadata(&self, token_id: &TokenId) -> Option<TokenMetadata> {
+        self.tokens
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(token_id))
+            .map(public_metadata)
+    }
+}
+
+/// Keeps only the fields of `metadata` that are public: the dates the contract itself
+/// maintains. Everything else belongs in the encrypted metadata.
+pub(crate) fn public_metadata(metadata: TokenMetadata) -> TokenMetadata {
+    TokenMetadata {
+        title: None,
+        description: None,
+        media: None,
+        media_hash: None,
+        copies: None,
+        issued_at: metadata.issued_at,
+        expires_at: metadata.expires_at,
+        starts_at: metadata.starts_at,
+        updated_at: metadata.updated_at,
+        extra: None,
+        reference: None,
+        reference_hash: None,
+    }
+}
+
+fn verify_signature(public_key: &PublicKey, message: &[u8], signature: &[u8]) -> bool {
+    // the first byte of a near `PublicKey` is its curve type
+    let public_key = ed25519_dalek::PublicKey::from_bytes(&public_key.as_bytes()[1..]);
+    let signature = ed25519_dalek::Signature::try_from(signature);
+    match (public_key, signature) {
+        (Ok(public_key), Ok(signature)) => public_key.verify(message, &signature).is_ok(),
+        _ => false,
+    }
+}
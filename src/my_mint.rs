@@ -1,60 +1,251 @@
 use near_contract_standards::non_fungible_token::{
     events::NftMint, refund_deposit_to_account, Token,
 };
-use near_sdk::{collections::UnorderedSet, near_bindgen, require};
+use near_sdk::{collections::UnorderedSet, near_bindgen};
+use std::collections::HashSet;
 
-use crate::{my_internal::parse_time, *};
+use crate::my_internal::{expiration_date, metadata_date, parse_timestamp};
+use crate::my_metadata::PrivateMetadata;
+use crate::{my_roles::Role, *};
+
+/// Maximum number of tokens minted by one `nft_batch_mint` call, so that a batch fits in
+/// the gas limit of a transaction.
+pub const MAX_BATCH_MINT_SIZE: usize = 50;
+
+/// `(token_id, receiver_id, token_metadata, expiration_period, perpetual_royalties,
+/// expires_at, starts_at)`
+pub type BatchMintEntry = (
+    TokenId,
+    AccountId,
+    PrivateMetadata,
+    Option<String>,
+    Option<HashMap<AccountId, u32>>,
+    Option<String>,
+    Option<String>,
+);
 
 #[near_bindgen]
 impl Contract {
+    /// Mints a token for `receiver_id`, with `token_metadata` encrypted off-chain (see
+    /// `PrivateMetadata`). Without `token_id` the contract generates the id
+    /// from its counter and the prefix set with `set_token_id_prefix`. The token expires
+    /// after `expiration_period` or at `expires_at` (Unix milliseconds or RFC 3339), if any,
+    /// and becomes active at `starts_at` (same format), if given.
+    #[allow(clippy::too_many_arguments)]
     #[payable]
     pub fn nft_mint(
         &mut self,
-        token_id: TokenId,
+        token_id: Option<TokenId>,
         receiver_id: AccountId,
-        token_metadata: TokenMetadata,
+        token_metadata: PrivateMetadata,
         expiration_period: Option<String>,
         perpetual_royalties: Option<HashMap<AccountId, u32>>,
+        expires_at: Option<String>,
+        starts_at: Option<String>,
     ) -> JsonToken {
-        require!(env::predecessor_account_id() == env::current_account_id());
-        // self.expiration_timestamp[token_id] = parse_time(expiration_period.unwrap());
-        // Remember current storage usage if refund_id is Some
+        self.assert_not_paused();
+        self.assert_role(Role::Minter);
+        self.use_minter_quota(&env::predecessor_account_id(), 1);
+        // Remember current storage usage to refund the deposit that isn't used for storage
         let initial_storage_usage = (env::predecessor_account_id(), env::storage_usage());
 
-        if self.tokens.owner_by_id.get(&token_id).is_some() {
-            env::panic_str("token_id must be unique");
+        let token_id = token_id.unwrap_or_else(|| self.internal_next_token_id());
+        let expiration_date = expiration_date(expiration_period.as_deref(), expires_at.as_deref());
+        let start_date = starts_at.map(|starts_at| parse_timestamp(&starts_at));
+        let token = self.internal_mint(
+            token_id,
+            receiver_id,
+            token_metadata,
+            expiration_date,
+            start_date,
+            perpetual_royalties,
+        );
+        NftMint {
+            owner_id: &token.owner_id,
+            token_ids: &[&token.token_id],
+            memo: None,
         }
+        .emit();
+
+        let (id, storage_usage) = initial_storage_usage;
+        refund_deposit_to_account(env::storage_usage() - storage_usage, id);
+        token
+    }
 
-        // create a royalty map to store in the token
-        let mut royalty = HashMap::new();
+    /// Mints every entry of `tokens` (same fields as `nft_mint`) in one call. All entries are
+    /// validated before anything is minted, the storage of the whole batch is charged at
+    /// once and a single `nft_mint` event is logged with one entry per owner.
+    #[payable]
+    pub fn nft_batch_mint(&mut self, tokens: Vec<BatchMintEntry>) -> Vec<JsonToken> {
+        self.assert_not_paused();
+        self.assert_role(Role::Minter);
+        require!(!tokens.is_empty(), "Nothing to mint");
+        require!(
+            tokens.len() <= MAX_BATCH_MINT_SIZE,
+            format!(
+                "Cannot mint more than {} tokens at once",
+                MAX_BATCH_MINT_SIZE
+            )
+        );
+        self.use_minter_quota(&env::predecessor_account_id(), tokens.len() as u64);
 
-        // if perpetual royalties were passed into the function:
-        if let Some(perpetual_royalties) = perpetual_royalties {
-            //make sure that the length of the perpetual royalties is below 7 since we won't have enough GAS to pay out that many people
-            assert!(
-                perpetual_royalties.len() < 7,
-                "Cannot add more than 6 perpetual royalty amounts"
+        let mut token_ids = HashSet::new();
+        let mut dates = Vec::with_capacity(tokens.len());
+        for (token_id, _, _, expiration_period, perpetual_royalties, expires_at, starts_at) in
+            tokens.iter()
+        {
+            require!(
+                self.tokens.owner_by_id.get(token_id).is_none() && token_ids.insert(token_id),
+                format!("token_id {} must be unique", token_id)
             );
+            dates.push((
+                expiration_date(expiration_period.as_deref(), expires_at.as_deref()),
+                starts_at.as_deref().map(parse_timestamp),
+            ));
+            if let Some(perpetual_royalties) = perpetual_royalties {
+                self.assert_valid_royalties(perpetual_royalties);
+            }
+        }
+
+        let initial_storage_usage = (env::predecessor_account_id(), env::storage_usage());
+        let minted: Vec<JsonToken> = tokens
+            .into_iter()
+            .zip(dates)
+            .map(|(entry, (expiration_date, start_date))| {
+                let (token_id, receiver_id, token_metadata, _, perpetual_royalties, _, _) = entry;
+                self.internal_mint(
+                    token_id,
+                    receiver_id,
+                    token_metadata,
+                    expiration_date,
+                    start_date,
+                    perpetual_royalties,
+                )
+            })
+            .collect();
 
-            //iterate through the perpetual royalties and insert the account and amount in the royalty map
-            for (account, amount) in perpetual_royalties {
-                royalty.insert(account, amount);
+        // group the minted ids by owner, keeping the order of the batch
+        let mut tokens_per_owner: Vec<(&AccountId, Vec<&str>)> = Vec::new();
+        for token in minted.iter() {
+            match tokens_per_owner
+                .iter_mut()
+                .find(|(owner_id, _)| **owner_id == token.owner_id)
+            {
+                Some((_, token_ids)) => token_ids.push(&token.token_id),
+                None => tokens_per_owner.push((&token.owner_id, vec![&token.token_id])),
             }
         }
-        self.royalty.insert(&token_id, &royalty);
+        let events: Vec<NftMint> = tokens_per_owner
+            .iter()
+            .map(|(owner_id, token_ids)| NftMint {
+                owner_id,
+                token_ids,
+                memo: None,
+            })
+            .collect();
+        NftMint::emit_many(&events);
+
+        let (id, storage_usage) = initial_storage_usage;
+        refund_deposit_to_account(env::storage_usage() - storage_usage, id);
+        minted
+    }
+
+    /// Sets the prefix of the token ids generated by the contract, e.g. `ticket-` for
+    /// `ticket-0`, `ticket-1`, ... The counter itself is never reset.
+    pub fn set_token_id_prefix(&mut self, prefix: String) {
+        self.assert_owner();
+        self.token_id_prefix = prefix;
+    }
+
+    pub fn get_token_id_prefix(&self) -> String {
+        self.token_id_prefix.clone()
+    }
+
+    /// Value of the counter the next generated token id is built from.
+    pub fn get_next_token_id(&self) -> u64 {
+        self.next_token_id
+    }
+}
+
+impl Contract {
+    /// Returns the next token id generated by the contract, skipping ids already taken by
+    /// explicitly named tokens.
+    pub(crate) fn internal_next_token_id(&mut self) -> TokenId {
+        loop {
+            let token_id = format!("{}{}", self.token_id_prefix, self.next_token_id);
+            self.next_token_id += 1;
+            if self.tokens.owner_by_id.get(&token_id).is_none() {
+                return token_id;
+            }
+        }
+    }
+
+    /// Mints a token without any access checks, storage accounting or events: the caller
+    /// is expected to do all three. The public `TokenMetadata` of the token is built by the
+    /// contract: `issued_at`, and `expires_at` and `starts_at` from `expiration_date` and
+    /// `start_date`.
+    pub(crate) fn internal_mint(
+        &mut self,
+        token_id: TokenId,
+        receiver_id: AccountId,
+        token_metadata: PrivateMetadata,
+        expiration_date: Option<u64>,
+        start_date: Option<u64>,
+        perpetual_royalties: Option<HashMap<AccountId, u32>>,
+    ) -> JsonToken {
+        require!(
+            self.tokens.owner_by_id.get(&token_id).is_none(),
+            "token_id must be unique"
+        );
+        token_metadata.assert_valid();
+
+        // if perpetual royalties were passed into the function, store them as the token's own
+        // royalty map; otherwise the token uses the collection default royalty
+        if let Some(perpetual_royalties) = perpetual_royalties {
+            self.assert_valid_royalties(&perpetual_royalties);
+            self.royalty.insert(&token_id, &perpetual_royalties);
+        }
 
         let owner_id: AccountId = receiver_id;
 
         // Core behavior: every token must have an owner
         self.tokens.owner_by_id.insert(&token_id, &owner_id);
+        // remember who paid for the storage so it can be refunded when the token is burned
+        self.minted_by
+            .insert(&token_id, &env::predecessor_account_id());
+
+        if let Some(expiration_date) = expiration_date {
+            self.expiration_timestamp
+                .insert(&token_id, &expiration_date);
+        }
+        if let Some(start_date) = start_date {
+            require!(
+                expiration_date.is_none_or(|expiration_date| start_date < expiration_date),
+                "Token must start before it expires"
+            );
+            self.start_timestamp.insert(&token_id, &start_date);
+        }
 
-        // Metadata extension: Save metadata, keep variable around to return later.
-        // Note that check above already panicked if metadata extension in use but no metadata
-        // provided to call.
+        // Metadata extension: only the dates are public, the metadata itself is kept encrypted
+        let public_metadata = TokenMetadata {
+            title: None,
+            description: None,
+            media: None,
+            media_hash: None,
+            copies: None,
+            issued_at: Some(metadata_date(env::block_timestamp())),
+            expires_at: expiration_date.map(metadata_date),
+            starts_at: start_date.map(metadata_date),
+            updated_at: None,
+            extra: None,
+            reference: None,
+            reference_hash: None,
+        };
         self.tokens
             .token_metadata_by_id
             .as_mut()
-            .and_then(|by_id| by_id.insert(&token_id, &token_metadata));
+            .and_then(|by_id| by_id.insert(&token_id, &public_metadata));
+        self.private_metadata.insert(&token_id, &token_metadata);
 
         // Enumeration extension: Record tokens_per_owner for use with enumeration view methods.
         if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
@@ -74,32 +265,18 @@ impl Contract {
             None
         };
 
-        // Return any extra attached deposit not used for storage
-
         let token = Token {
             token_id,
             owner_id,
-            metadata: Some(token_metadata),
+            metadata: Some(public_metadata),
             approved_account_ids,
         };
-        NftMint {
-            owner_id: &token.owner_id,
-            token_ids: &[&token.token_id],
-            memo: None,
-        }
-        .emit();
-        if let Some(time) = expiration_period {
-            self.expiration_timestamp.insert(
-                &token.token_id,
-                &(env::block_timestamp() + parse_time(&time)),
-            );
-        }
-
-        let (id, storage_usage) = initial_storage_usage;
-        refund_deposit_to_account(env::storage_usage() - storage_usage, id);
         JsonToken {
-            expiration_date: self.expiration_timestamp.get(&token.token_id),
-            royalty: royalty,
+            expiration_date,
+            start_date,
+            status: self.token_status(&token.token_id),
+            in_grace: self.token_in_grace(&token.token_id),
+            royalty: self.token_royalty(&token.token_id),
             token_id: token.token_id,
             owner_id: token.owner_id,
             metadata: token.metadata,
@@ -0,0 +1,121 @@
+use near_contract_standards::non_fungible_token::refund_deposit;
+use near_sdk::json_types::U128;
+use near_sdk::{near_bindgen, require};
+
+use crate::my_events::NftRenew;
+use crate::my_internal::{parse_time, parse_timestamp};
+use crate::my_roles::Role;
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Pushes the expiration date of `token_id` forward by `extension_period` (same format as
+    /// `expiration_period` in `nft_mint`) or to `expires_at` (Unix milliseconds or RFC 3339).
+    /// Can be called by the token owner or by an authorized renewer; the attached deposit
+    /// must cover the renewal price, which goes to the contract owner. A token in its grace
+    /// period can still be renewed, from the current block time rather than from its past
+    /// expiration date. Returns the new expiration date.
+    #[payable]
+    pub fn nft_renew(
+        &mut self,
+        token_id: TokenId,
+        extension_period: Option<String>,
+        expires_at: Option<String>,
+    ) -> u64 {
+        self.assert_not_paused();
+        let owner_id = self.tokens.owner_by_id.get(&token_id);
+        require!(owner_id.is_some(), "Token not found");
+        let owner_id = owner_id.unwrap();
+
+        let predecessor_id = env::predecessor_account_id();
+        require!(
+            predecessor_id == owner_id || self.renewers.contains(&predecessor_id),
+            "Only the token owner or a renewer can renew the token"
+        );
+
+        let expiration_date = self.expiration_timestamp.get(&token_id);
+        require!(expiration_date.is_some(), "Token has no expiration date");
+        require!(!self.token_is_expired(&token_id), "Token is expired");
+
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= self.renewal_price,
+            format!(
+                "Must attach {} yoctoNEAR to renew the token",
+                self.renewal_price
+            )
+        );
+
+        // a token in its grace period is renewed from now, not from when it expired
+        let expiration_date = expiration_date.unwrap().max(env::block_timestamp());
+        let new_expiration_date = if let Some(expires_at) = expires_at {
+            require!(
+                extension_period.is_none(),
+                "Only one of extension_period and expires_at can be given"
+            );
+            let new_expiration_date = parse_timestamp(&expires_at);
+            require!(
+                new_expiration_date > expiration_date,
+                "New expiration date must be later than the current one and than now"
+            );
+            new_expiration_date
+        } else {
+            require!(
+                extension_period.is_some(),
+                "Either extension_period or expires_at must be given"
+            );
+            let new_expiration_date =
+                expiration_date.checked_add(parse_time(&extension_period.unwrap()));
+            require!(
+                new_expiration_date.is_some(),
+                "Expiration date is too far in the future"
+            );
+            new_expiration_date.unwrap()
+        };
+        self.internal_set_expiration(&token_id, new_expiration_date);
+
+        if self.renewal_price > 0 {
+            Promise::new(self.tokens.owner_id.clone()).transfer(self.renewal_price);
+        }
+        let refund = deposit - self.renewal_price;
+        if refund > 0 {
+            Promise::new(predecessor_id.clone()).transfer(refund);
+        }
+
+        NftRenew {
+            owner_id: &owner_id,
+            token_id: &token_id,
+            expiration_date: new_expiration_date,
+            authorized_id: Some(&predecessor_id).filter(|id| **id != owner_id),
+        }
+        .emit();
+        new_expiration_date
+    }
+
+    pub fn set_renewal_price(&mut self, price: U128) {
+        self.assert_role(Role::Admin);
+        self.renewal_price = price.into();
+    }
+
+    pub fn get_renewal_price(&self) -> U128 {
+        U128(self.renewal_price)
+    }
+
+    /// Allows `account_id` to renew any token of the collection.
+    #[payable]
+    pub fn add_renewer(&mut self, account_id: AccountId) {
+        self.assert_role(Role::Admin);
+        let initial_storage_usage = env::storage_usage();
+        self.renewers.insert(&account_id);
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+    }
+
+    pub fn remove_renewer(&mut self, account_id: AccountId) {
+        self.assert_role(Role::Admin);
+        self.renewers.remove(&account_id);
+    }
+
+    pub fn is_renewer(&self, account_id: AccountId) -> bool {
+        self.renewers.contains(&account_id)
+    }
+}
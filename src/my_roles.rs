@@ -0,0 +1,142 @@
+use near_contract_standards::non_fungible_token::refund_deposit;
+use near_sdk::serde::Deserialize;
+use near_sdk::{near_bindgen, require};
+
+use crate::my_events::{RoleGranted, RoleRevoked};
+use crate::*;
+
+/// Roles that can be granted to accounts. The contract owner (and the contract account
+/// itself) implicitly has every role, and so does any account granted `Owner`.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Owner,
+    Admin,
+    Minter,
+    Pauser,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Admin => "admin",
+            Role::Minter => "minter",
+            Role::Pauser => "pauser",
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Owners can grant any role, admins can grant `minter` and `pauser`.
+    #[payable]
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_can_manage_role(role);
+        let initial_storage_usage = env::storage_usage();
+        if self.roles.insert(&(role, account_id.clone())) {
+            RoleGranted {
+                account_id: &account_id,
+                role: role.as_str(),
+                authorized_id: &env::predecessor_account_id(),
+            }
+            .emit();
+        }
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+    }
+
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_can_manage_role(role);
+        if self.roles.remove(&(role, account_id.clone())) {
+            if role == Role::Minter {
+                self.minter_quotas.remove(&account_id);
+            }
+            RoleRevoked {
+                account_id: &account_id,
+                role: role.as_str(),
+                authorized_id: &env::predecessor_account_id(),
+            }
+            .emit();
+        }
+    }
+
+    pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+        self.internal_has_role(&account_id, role)
+    }
+
+    /// Limits how many more tokens `account_id` can mint; `None` removes the limit.
+    #[payable]
+    pub fn set_minter_quota(&mut self, account_id: AccountId, quota: Option<u64>) {
+        self.assert_can_manage_role(Role::Minter);
+        let initial_storage_usage = env::storage_usage();
+        match quota {
+            Some(quota) => self.minter_quotas.insert(&account_id, &quota),
+            None => self.minter_quotas.remove(&account_id),
+        };
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Number of tokens `account_id` can still mint, `None` if unlimited.
+    pub fn get_minter_quota(&self, account_id: AccountId) -> Option<u64> {
+        self.minter_quotas.get(&account_id)
+    }
+
+    pub fn pause(&mut self) {
+        self.assert_role(Role::Pauser);
+        self.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.assert_role(Role::Pauser);
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_has_role(&self, account_id: &AccountId, role: Role) -> bool {
+        *account_id == self.tokens.owner_id
+            || *account_id == env::current_account_id()
+            || self.roles.contains(&(Role::Owner, account_id.clone()))
+            || self.roles.contains(&(role, account_id.clone()))
+    }
+
+    pub(crate) fn assert_role(&self, role: Role) {
+        require!(
+            self.internal_has_role(&env::predecessor_account_id(), role),
+            format!("Requires the {} role", role.as_str())
+        );
+    }
+
+    pub(crate) fn assert_owner(&self) {
+        require!(
+            self.internal_has_role(&env::predecessor_account_id(), Role::Owner),
+            "Only the contract owner can call this method"
+        );
+    }
+
+    pub(crate) fn assert_not_paused(&self) {
+        require!(!self.paused, "Contract is paused");
+    }
+
+    /// Takes `count` mints from the quota of the caller, if they have one.
+    pub(crate) fn use_minter_quota(&mut self, minter_id: &AccountId, count: u64) {
+        if let Some(quota) = self.minter_quotas.get(minter_id) {
+            require!(quota >= count, "Minting quota exhausted");
+            self.minter_quotas.insert(minter_id, &(quota - count));
+        }
+    }
+
+    fn assert_can_manage_role(&self, role: Role) {
+        match role {
+            Role::Owner | Role::Admin => self.assert_owner(),
+            Role::Minter | Role::Pauser => self.assert_role(Role::Admin),
+        }
+    }
+}
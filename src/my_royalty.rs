@@ -1,7 +1,14 @@
-use near_sdk::{assert_one_yocto, json_types::U128, serde::Deserialize};
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+use near_contract_standards::non_fungible_token::refund_deposit;
+use near_sdk::{assert_one_yocto, json_types::U128, require, serde::Deserialize};
 
+use crate::my_events::NftRoyaltyTransfer;
+use crate::my_roles::Role;
 use crate::{my_internal::royalty_to_payout, *};
 
+/// 100% in basis points.
+pub const MAX_TOTAL_ROYALTY: u32 = 10_000;
+
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Payout {
@@ -30,6 +37,8 @@ impl Payouts for Contract {
             .owner_by_id
             .get(&token_id)
             .expect("Token doesn't exist.");
+        //expired tokens can't be sold
+        self.assert_token_active(&token_id);
         //keep track of the total perpetual royalties
         let mut total_perpetual = 0;
         //get the u128 version of the passed in balance (which was U128 before)
@@ -39,7 +48,7 @@ impl Payouts for Contract {
             payout: HashMap::new(),
         };
         //get the royalty object from token
-        let royalty = self.royalty.get(&token_id).unwrap();
+        let royalty = self.token_royalty(&token_id);
 
         //make sure we're not paying out to too many people (GAS limits this)
         assert!(
@@ -63,7 +72,7 @@ impl Payouts for Contract {
         // payout to previous owner who gets 100% - total perpetual royalties
         payout_object.payout.insert(
             owner_id,
-            royalty_to_payout(10000 - total_perpetual, balance_u128),
+            royalty_to_payout(MAX_TOTAL_ROYALTY - total_perpetual, balance_u128),
         );
 
         //return the payout object
@@ -81,8 +90,99 @@ impl Payouts for Contract {
         max_len_payout: u32,
     ) -> Payout {
         assert_one_yocto();
+        self.assert_not_paused();
+        self.assert_token_active(&token_id);
         let payout = self.nft_payout(token_id.clone(), balance, max_len_payout);
         self.nft_transfer(receiver_id, token_id, Some(approval_id), memo);
         payout
     }
 }
+
+#[near_bindgen]
+impl Contract {
+    /// Maximum total perpetual royalty of a token, in basis points.
+    pub fn nft_max_royalty(&self) -> u32 {
+        self.max_royalty
+    }
+
+    pub fn nft_royalties(&self, token_id: TokenId) -> HashMap<AccountId, u32> {
+        require!(
+            self.tokens.owner_by_id.contains_key(&token_id),
+            "Token not found"
+        );
+        self.token_royalty(&token_id)
+    }
+
+    pub fn nft_default_royalty(&self) -> HashMap<AccountId, u32> {
+        self.default_royalty.get().unwrap_or_default()
+    }
+
+    /// Sets the royalty used by every token that wasn't given its own royalty at mint.
+    #[payable]
+    pub fn set_default_royalty(&mut self, royalty: HashMap<AccountId, u32>) {
+        self.assert_role(Role::Admin);
+        self.assert_valid_royalties(&royalty);
+        let initial_storage_usage = env::storage_usage();
+        if royalty.is_empty() {
+            self.default_royalty.remove();
+        } else {
+            self.default_royalty.set(&royalty);
+        }
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Moves `basis_points` of the royalty of `from` to `to`. Only `from` can call this; the
+    /// total royalty of the token stays the same. The attached deposit must cover the
+    /// storage of a new royalty entry. A token using the default royalty gets its own copy.
+    #[payable]
+    pub fn nft_royalty_transfer(
+        &mut self,
+        token_id: TokenId,
+        from: AccountId,
+        to: AccountId,
+        basis_points: u32,
+    ) {
+        require!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        require!(
+            env::predecessor_account_id() == from,
+            "Only the royalty holder can transfer it"
+        );
+        require!(from != to, "Royalty holders must differ");
+        require!(basis_points > 0, "Cannot transfer 0 basis points");
+
+        let mut royalty = self.nft_royalties(token_id.clone());
+        let from_amount = royalty.get(&from).copied().unwrap_or_default();
+        require!(
+            from_amount >= basis_points,
+            format!(
+                "{} holds only {} basis points of the royalty",
+                from, from_amount
+            )
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        if from_amount == basis_points {
+            royalty.remove(&from);
+        } else {
+            royalty.insert(from.clone(), from_amount - basis_points);
+        }
+        *royalty.entry(to.clone()).or_default() += basis_points;
+        assert!(
+            royalty.len() < 7,
+            "Cannot add more than 6 perpetual royalty amounts"
+        );
+        self.royalty.insert(&token_id, &royalty);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        NftRoyaltyTransfer {
+            token_id: &token_id,
+            old_holder_id: &from,
+            new_holder_id: &to,
+            basis_points,
+        }
+        .emit();
+    }
+}
@@ -0,0 +1,249 @@
+use near_contract_standards::non_fungible_token::events::NftMint;
+use near_contract_standards::non_fungible_token::refund_deposit;
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::Deserialize;
+use near_sdk::{near_bindgen, require};
+
+use crate::my_internal::{expiration_date, parse_timestamp};
+use crate::my_metadata::PrivateMetadata;
+use crate::*;
+
+/// Maximum number of tokens bought in one `nft_mint_public` call, so that a single
+/// purchase can't run out of gas.
+pub const MAX_PUBLIC_MINT_COUNT: u64 = 20;
+
+/// Who can buy from the sale when it isn't public.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Allowlist {
+    /// Accounts added with `add_to_allowlist`.
+    Accounts,
+    /// Root of a merkle tree whose leaves are `sha256(account_id)`; pairs of nodes are
+    /// hashed in sorted order.
+    MerkleRoot(Base64VecU8),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleConfig {
+    /// Price of one token in yoctoNEAR, on top of its storage.
+    pub price: U128,
+    /// Total number of tokens the sale can sell.
+    pub max_supply: u64,
+    /// Maximum number of tokens a single account can buy.
+    pub per_account_limit: Option<u64>,
+    /// Nanoseconds.
+    pub starts_at: Option<u64>,
+    /// Nanoseconds.
+    pub ends_at: Option<u64>,
+    pub allowlist: Option<Allowlist>,
+    /// Receives the proceeds of the sale.
+    pub treasury_id: AccountId,
+    /// Encrypted metadata of every sold token, see `PrivateMetadata`.
+    pub token_metadata: PrivateMetadata,
+    /// Lifetime of every sold token, same format as in `nft_mint`.
+    pub expiration_period: Option<String>,
+    /// Date every sold token expires at, same format as `expires_at` in `nft_mint`; can't be
+    /// combined with `expiration_period`.
+    pub token_expires_at: Option<String>,
+    /// Date every sold token becomes active at, same format as `starts_at` in `nft_mint`.
+    pub token_starts_at: Option<String>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Starts a sale or replaces the config of the current one. Tokens already sold (in
+    /// total and per account) keep counting against the limits of the new config.
+    #[payable]
+    pub fn set_sale(&mut self, sale: SaleConfig) {
+        self.assert_owner();
+        require!(sale.max_supply > 0, "Sale max supply must be positive");
+        sale.token_metadata.assert_valid();
+        if let (Some(starts_at), Some(ends_at)) = (sale.starts_at, sale.ends_at) {
+            require!(starts_at < ends_at, "Sale must start before it ends");
+        }
+        // validates the dates now rather than on every purchase
+        let token_expiration_date = expiration_date(
+            sale.expiration_period.as_deref(),
+            sale.token_expires_at.as_deref(),
+        );
+        if let Some(token_starts_at) = &sale.token_starts_at {
+            let token_start_date = parse_timestamp(token_starts_at);
+            require!(
+                token_expiration_date
+                    .is_none_or(|expiration_date| token_start_date < expiration_date),
+                "Token must start before it expires"
+            );
+        }
+        let initial_storage_usage = env::storage_usage();
+        self.sale.set(&sale);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn remove_sale(&mut self) {
+        self.assert_owner();
+        self.sale.remove();
+    }
+
+    pub fn get_sale(&self) -> Option<SaleConfig> {
+        self.sale.get()
+    }
+
+    pub fn get_sale_sold(&self) -> u64 {
+        self.sale_sold
+    }
+
+    pub fn get_sale_minted(&self, account_id: AccountId) -> u64 {
+        self.sale_minted.get(&account_id).unwrap_or_default()
+    }
+
+    /// Adds accounts to the allowlist used when the sale allowlist is `accounts`.
+    #[payable]
+    pub fn add_to_allowlist(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        let initial_storage_usage = env::storage_usage();
+        for account_id in account_ids.iter() {
+            self.sale_allowlist.insert(account_id);
+        }
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+    }
+
+    pub fn remove_from_allowlist(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids.iter() {
+            self.sale_allowlist.remove(account_id);
+        }
+    }
+
+    pub fn is_allowlisted(&self, account_id: AccountId) -> bool {
+        self.sale_allowlist.contains(&account_id)
+    }
+
+    /// Buys `count` tokens from the sale for `receiver_id`. The attached deposit must cover
+    /// `price * count` plus the storage of the new tokens; the price goes to the treasury and
+    /// the rest is refunded. `merkle_proof` is only needed for a merkle root allowlist.
+    #[payable]
+    pub fn nft_mint_public(
+        &mut self,
+        receiver_id: AccountId,
+        count: u64,
+        merkle_proof: Option<Vec<Base64VecU8>>,
+    ) -> Vec<JsonToken> {
+        self.assert_not_paused();
+        let sale = self.sale.get();
+        require!(sale.is_some(), "No sale is configured");
+        let sale = sale.unwrap();
+        require!(
+            count > 0 && count <= MAX_PUBLIC_MINT_COUNT,
+            format!(
+                "Can buy from 1 to {} tokens at a time",
+                MAX_PUBLIC_MINT_COUNT
+            )
+        );
+
+        let now = env::block_timestamp();
+        require!(
+            sale.starts_at.is_none_or(|starts_at| now >= starts_at),
+            "Sale has not started"
+        );
+        require!(
+            sale.ends_at.is_none_or(|ends_at| now < ends_at),
+            "Sale has ended"
+        );
+        require!(
+            self.sale_sold + count <= sale.max_supply,
+            "Not enough tokens left in the sale"
+        );
+
+        let buyer_id = env::predecessor_account_id();
+        match &sale.allowlist {
+            Some(Allowlist::Accounts) => require!(
+                self.sale_allowlist.contains(&buyer_id),
+                "Account is not on the allowlist"
+            ),
+            Some(Allowlist::MerkleRoot(root)) => require!(
+                verify_merkle_proof(&buyer_id, &merkle_proof.unwrap_or_default(), &root.0),
+                "Account is not on the allowlist"
+            ),
+            None => {}
+        }
+        let bought = self.get_sale_minted(buyer_id.clone()) + count;
+        if let Some(limit) = sale.per_account_limit {
+            require!(
+                bought <= limit,
+                format!("Can't buy more than {} tokens per account", limit)
+            );
+        }
+
+        let expiration_date = expiration_date(
+            sale.expiration_period.as_deref(),
+            sale.token_expires_at.as_deref(),
+        );
+        let start_date = sale.token_starts_at.as_deref().map(parse_timestamp);
+        let initial_storage_usage = env::storage_usage();
+        self.sale_sold += count;
+        self.sale_minted.insert(&buyer_id, &bought);
+        let tokens: Vec<JsonToken> = (0..count)
+            .map(|_| {
+                let token_id = self.internal_next_token_id();
+                // the buyer pays for the storage, but the token is issued by the contract
+                self.issued_by.insert(&token_id, &env::current_account_id());
+                self.internal_mint(
+                    token_id,
+                    receiver_id.clone(),
+                    sale.token_metadata.clone(),
+                    expiration_date,
+                    start_date,
+                    None,
+                )
+            })
+            .collect();
+
+        let storage_cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        let proceeds = sale.price.0.checked_mul(count as Balance);
+        require!(proceeds.is_some(), "Sale price overflow");
+        let proceeds = proceeds.unwrap();
+        let required_deposit = proceeds + storage_cost;
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= required_deposit,
+            format!(
+                "Must attach at least {} yoctoNEAR to buy {} tokens",
+                required_deposit, count
+            )
+        );
+
+        if proceeds > 0 {
+            Promise::new(sale.treasury_id).transfer(proceeds);
+        }
+        let refund = deposit - required_deposit;
+        if refund > 0 {
+            Promise::new(buyer_id).transfer(refund);
+        }
+
+        let token_ids: Vec<&str> = tokens.iter().map(|token| token.token_id.as_str()).collect();
+        NftMint {
+            owner_id: &receiver_id,
+            token_ids: &token_ids,
+            memo: None,
+        }
+        .emit();
+        tokens
+    }
+}
+
+/// Checks that `sha256(account_id)` is a leaf of the merkle tree with the given `root`.
+fn verify_merkle_proof(account_id: &AccountId, proof: &[Base64VecU8], root: &[u8]) -> bool {
+    let leaf = env::sha256(account_id.as_bytes());
+    let computed_root = proof.iter().fold(leaf, |node, sibling| {
+        let (left, right) = if node <= sibling.0 {
+            (&node, &sibling.0)
+        } else {
+            (&sibling.0, &node)
+        };
+        env::sha256(&[left.as_slice(), right.as_slice()].concat())
+    });
+    computed_root == root
+}
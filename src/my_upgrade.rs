@@ -0,0 +1,117 @@
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{near_bindgen, require, Gas};
+
+use crate::*;
+
+/// Version of the state layout written by this code. Bump it whenever a released version
+/// changes the fields of `Contract`, and teach `migrate` to convert from the previous layout.
+pub const STATE_VERSION: u8 = 2;
+/// Storage key of the state version. The state of version 1 doesn't have it.
+pub(crate) const STATE_VERSION_KEY: &[u8] = b"VERSION";
+
+const GAS_FOR_MIGRATE: Gas = Gas(100_000_000_000_000);
+
+/// Version 1: the layout before roles, sales, collections and the other extensions.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct ContractV1 {
+    pub tokens: NonFungibleToken,
+    pub metadata: LazyOption<NFTContractMetadata>,
+    pub expiration_timestamp: LookupMap<TokenId, u64>,
+    pub royalty: LookupMap<TokenId, HashMap<AccountId, u32>>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Converts the state left by a previous version of the contract to the current layout.
+    /// Called by `upgrade` right after the new code is deployed; can also be called by the
+    /// contract owner after a manual deploy. Does nothing if the state is already current.
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let contract = match state_version() {
+            1 => {
+                let old: ContractV1 = env::state_read().expect("Contract state not found");
+                assert_can_migrate(&old.tokens.owner_id);
+                Self::from_v1(old)
+            }
+            STATE_VERSION => {
+                let contract: Self = env::state_read().expect("Contract state not found");
+                assert_can_migrate(&contract.tokens.owner_id);
+                contract
+            }
+            version => env::panic_str(&format!("Unknown state version {}", version)),
+        };
+        write_state_version();
+        contract
+    }
+
+    /// Deploys `code` to this account and migrates the state with it. Both happen in the
+    /// same receipt, so a failed migration leaves the old code in place.
+    pub fn upgrade(&mut self, code: Base64VecU8) -> Promise {
+        self.assert_owner();
+        Promise::new(env::current_account_id())
+            .deploy_contract(code.into())
+            .function_call("migrate".to_string(), vec![], 0, GAS_FOR_MIGRATE)
+    }
+
+    pub fn get_state_version(&self) -> u8 {
+        state_version()
+    }
+}
+
+impl Contract {
+    fn from_v1(old: ContractV1) -> Self {
+        Self {
+            tokens: old.tokens,
+            metadata: old.metadata,
+            expiration_timestamp: old.expiration_timestamp,
+            // tokens of version 1 always store a (possibly empty) royalty map, so the
+            // default royalty never applies to them
+            royalty: old.royalty,
+            collections: UnorderedMap::new(StorageKey::Collections),
+            renewers: LookupSet::new(StorageKey::Renewers),
+            renewal_price: 0,
+            // the contract account was the only minter in version 1, so purges refund to it
+            minted_by: LookupMap::new(StorageKey::MintedBy),
+            max_royalty: my_royalty::MAX_TOTAL_ROYALTY,
+            default_royalty: LazyOption::new(StorageKey::DefaultRoyalty, None),
+            roles: LookupSet::new(StorageKey::Roles),
+            minter_quotas: LookupMap::new(StorageKey::MinterQuotas),
+            paused: false,
+            sale: LazyOption::new(StorageKey::Sale, None),
+            sale_sold: 0,
+            sale_minted: LookupMap::new(StorageKey::SaleMinted),
+            sale_allowlist: LookupSet::new(StorageKey::SaleAllowlist),
+            next_token_id: 0,
+            token_id_prefix: String::new(),
+            metadata_history: LookupMap::new(StorageKey::MetadataHistory),
+            start_timestamp: LookupMap::new(StorageKey::StartTimestamp),
+            // tokens expired before the upgrade keep being hidden and purgeable
+            expiry_policy: None,
+            token_expiry_policies: LookupMap::new(StorageKey::TokenExpiryPolicies),
+            grace_period: 0,
+            token_grace_periods: LookupMap::new(StorageKey::TokenGracePeriods),
+            private_metadata: LookupMap::new(StorageKey::PrivateMetadata),
+            metadata_keys: LookupMap::new(StorageKey::MetadataKeys),
+            expired_tokens: LookupSet::new(StorageKey::ExpiredTokens),
+            expired_supply: 0,
+            expired_supply_per_owner: LookupMap::new(StorageKey::ExpiredSupplyPerOwner),
+            issued_by: LookupMap::new(StorageKey::IssuedBy),
+        }
+    }
+}
+
+pub(crate) fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &[STATE_VERSION]);
+}
+
+fn state_version() -> u8 {
+    env::storage_read(STATE_VERSION_KEY).map_or(1, |version| version[0])
+}
+
+fn assert_can_migrate(owner_id: &AccountId) {
+    let predecessor_id = env::predecessor_account_id();
+    require!(
+        predecessor_id == env::current_account_id() || predecessor_id == *owner_id,
+        "Only the contract owner can call this method"
+    );
+}